@@ -15,6 +15,7 @@ use bevy_simple_subsecond_system::SimpleSubsecondPlugin;
 
 use crate::{
     Pause,
+    game::solver::SolveLevel,
     menu::Menu,
     state::{GameState, ProgramState},
 };
@@ -57,10 +58,15 @@ pub(super) fn plugin(app: &mut App) {
     // Toggle the debug overlay for UI.
     app.add_observer(debug_binding);
     app.add_observer(toggle_debug_ui);
+    // Log the shortest program for the current level, to check it's solvable.
+    app.add_observer(solve_binding);
+    app.add_observer(solve_level);
 }
 
 const TOGGLE_KEY: KeyCode = KeyCode::Backquote;
 
+const SOLVE_KEY: KeyCode = KeyCode::F5;
+
 #[derive(InputAction, Reflect, Default, Debug)]
 #[input_action(output = bool, require_reset = true)]
 struct ToggleDebug;
@@ -79,3 +85,24 @@ fn debug_binding(
 fn toggle_debug_ui(_: Trigger<Fired<ToggleDebug>>, mut options: ResMut<UiDebugOptions>) {
     options.toggle();
 }
+
+#[derive(InputAction, Reflect, Default, Debug)]
+#[input_action(output = bool, require_reset = true)]
+struct Solve;
+
+fn solve_binding(
+    trigger: Trigger<Binding<DebugContext>>,
+    mut actions: Query<&mut Actions<DebugContext>>,
+) {
+    let mut actions = actions.get_mut(trigger.target()).unwrap();
+    actions
+        .bind::<Solve>()
+        .to(SOLVE_KEY)
+        .with_conditions(Press::new(0.2));
+}
+
+fn solve_level(_: Trigger<Fired<Solve>>, mut commands: Commands) {
+    commands.trigger(SolveLevel {
+        load_solution: false,
+    });
+}
@@ -0,0 +1,280 @@
+//! Static checks of a program, so obvious mistakes show up in the program
+//! panel before the robot wanders into them.
+
+use std::{fmt, time::Duration};
+
+use petgraph::{algo::tarjan_scc, graph::DiGraph};
+
+use super::cpu::{Action, CpuOptions, Instruction};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// No path from the first slot reaches this instruction.
+    Unreachable { slot: usize },
+    /// The instruction jumps past the end of the program, which ends it.
+    EmptyTarget { slot: usize, target: usize },
+    /// Once entered, the loop starting at this slot can never be left, and
+    /// nothing in it moves the robot.
+    NoMovementLoop { slot: usize },
+    /// Even if it doesn't loop, the program may run longer than the bomb.
+    MayOutlastBomb {
+        worst_case: Duration,
+        bomb: Duration,
+    },
+}
+
+impl Warning {
+    /// The slot the warning is about, if it's about a single one.
+    pub fn slot(&self) -> Option<usize> {
+        match self {
+            Warning::Unreachable { slot }
+            | Warning::EmptyTarget { slot, .. }
+            | Warning::NoMovementLoop { slot } => Some(*slot),
+            Warning::MayOutlastBomb { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::Unreachable { .. } => write!(f, "never runs"),
+            Warning::EmptyTarget { target, .. } => write!(f, "jumps to empty slot {target}"),
+            Warning::NoMovementLoop { .. } => write!(f, "loops forever without moving"),
+            Warning::MayOutlastBomb { worst_case, bomb } => write!(
+                f,
+                "may take {worst_case:?}, but the bomb goes off after {bomb:?}"
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Analysis {
+    pub warnings: Vec<Warning>,
+    /// The most ticks the program can take, or `None` if it can loop.
+    pub worst_case_ticks: Option<u64>,
+}
+
+impl Analysis {
+    pub fn warning_for(&self, slot: usize) -> Option<&Warning> {
+        self.warnings
+            .iter()
+            .find(|warning| warning.slot() == Some(slot))
+    }
+}
+
+/// The slots execution can continue at after `slot`. `code.len()` or anything
+/// past it means the program ends.
+fn successors(code: &[Instruction], slot: usize) -> Vec<usize> {
+    let next = slot + 1;
+    match code[slot] {
+        Instruction::Jump(target) => vec![target],
+        Instruction::JumpIfBlocked(target)
+        | Instruction::JumpIfZero(_, target)
+        | Instruction::Call(target)
+        | Instruction::OnInterrupt(_, target)
+        | Instruction::If(_, Action::Jump(target)) => vec![next, target],
+        // Where a `Return` goes is covered by the slot after each `Call`.
+        Instruction::Halt | Instruction::Return => vec![],
+        _ => vec![next],
+    }
+}
+
+pub fn analyze(code: &[Instruction], options: &CpuOptions, time_to_bomb: Duration) -> Analysis {
+    let mut analysis = Analysis::default();
+    if code.is_empty() {
+        analysis.worst_case_ticks = Some(0);
+        return analysis;
+    }
+
+    // Build the control flow graph of everything reachable from the first slot.
+    // Running off the end either ends the program or starts it over.
+    let end = code.len();
+    let follow = |next: usize| {
+        if next < end {
+            next
+        } else if options.loop_program {
+            0
+        } else {
+            end
+        }
+    };
+    let mut graph = DiGraph::<usize, ()>::new();
+    let nodes = (0..=end)
+        .map(|slot| graph.add_node(slot))
+        .collect::<Vec<_>>();
+    let mut reachable = vec![false; end];
+    let mut to_visit = vec![0];
+    while let Some(slot) = to_visit.pop() {
+        if slot >= end || reachable[slot] {
+            continue;
+        }
+        reachable[slot] = true;
+        for next in successors(code, slot).into_iter().map(follow) {
+            graph.add_edge(nodes[slot], nodes[next], ());
+            to_visit.push(next);
+        }
+    }
+
+    for slot in 0..end {
+        if !reachable[slot] {
+            analysis.warnings.push(Warning::Unreachable { slot });
+            continue;
+        }
+        if let Some(target) = code[slot].target().filter(|target| *target >= end) {
+            analysis
+                .warnings
+                .push(Warning::EmptyTarget { slot, target });
+        }
+    }
+
+    // `tarjan_scc` lists components in reverse topological order, so every
+    // slot's successors have been costed by the time it is. The costs only
+    // mean anything if nothing loops.
+    let mut worst_case = vec![0_u64; end + 1];
+    let mut loops = false;
+    for component in tarjan_scc(&graph) {
+        let slots = component
+            .iter()
+            .map(|node| graph[*node])
+            .filter(|slot| *slot < end && reachable[*slot])
+            .collect::<Vec<_>>();
+        let Some(&first) = slots.iter().min() else {
+            continue;
+        };
+        let next_slots = |slot: usize| successors(code, slot).into_iter().map(follow);
+
+        let is_loop = slots.len() > 1 || next_slots(first).any(|next| next == first);
+        if is_loop {
+            loops = true;
+            let closed = slots
+                .iter()
+                .all(|slot| next_slots(*slot).all(|next| slots.contains(&next)));
+            let moves = slots.iter().any(|slot| {
+                matches!(
+                    code[*slot],
+                    Instruction::MoveForward | Instruction::If(_, Action::MoveForward)
+                )
+            });
+            if closed && !moves {
+                analysis
+                    .warnings
+                    .push(Warning::NoMovementLoop { slot: first });
+            }
+            continue;
+        }
+
+        let cost = u64::from(options.cycle_costs.cost(&code[first]));
+        worst_case[first] = match code[first] {
+            // A call runs the subroutine down to its `Return`, and then
+            // carries on after the call, so both paths add up.
+            Instruction::Call(target) => {
+                cost + worst_case[follow(target)] + worst_case[follow(first + 1)]
+            }
+            _ => next_slots(first)
+                .map(|next| worst_case[next])
+                .max()
+                .map_or(cost, |longest| cost + longest),
+        };
+    }
+
+    if !loops {
+        analysis.worst_case_ticks = Some(worst_case[0]);
+    }
+    if let Some(ticks) = analysis.worst_case_ticks {
+        let worst_case = options.cpu_tick * u32::try_from(ticks).unwrap_or(u32::MAX);
+        if worst_case > time_to_bomb {
+            analysis.warnings.push(Warning::MayOutlastBomb {
+                worst_case,
+                bomb: time_to_bomb,
+            });
+        }
+    }
+    analysis
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::game::cpu::{CycleCosts, Register};
+
+    const BOMB: Duration = Duration::from_secs(60);
+
+    /// Options where every instruction takes a single 100ms cycle.
+    fn options() -> CpuOptions {
+        CpuOptions {
+            cpu_tick: Duration::from_millis(100),
+            multiplier: 1.0,
+            max_call_depth: 4,
+            cycle_costs: CycleCosts(HashMap::new()),
+            loop_program: false,
+        }
+    }
+
+    #[test]
+    fn skipped_slots_are_unreachable() {
+        let code = [
+            Instruction::Jump(2),
+            Instruction::MoveForward,
+            Instruction::Halt,
+        ];
+        let analysis = analyze(&code, &options(), BOMB);
+        assert_eq!(analysis.warnings, vec![Warning::Unreachable { slot: 1 }]);
+        assert_eq!(analysis.worst_case_ticks, Some(2));
+    }
+
+    #[test]
+    fn jumps_past_the_end_hit_an_empty_slot() {
+        let code = [Instruction::JumpIfBlocked(5), Instruction::MoveForward];
+        let analysis = analyze(&code, &options(), BOMB);
+        assert_eq!(
+            analysis.warnings,
+            vec![Warning::EmptyTarget { slot: 0, target: 5 }]
+        );
+    }
+
+    #[test]
+    fn closed_loops_must_move() {
+        let code = [Instruction::Inc(Register::R0), Instruction::Jump(0)];
+        let analysis = analyze(&code, &options(), BOMB);
+        assert_eq!(analysis.warnings, vec![Warning::NoMovementLoop { slot: 0 }]);
+        assert_eq!(analysis.worst_case_ticks, None);
+
+        let code = [Instruction::MoveForward, Instruction::Jump(0)];
+        assert!(analyze(&code, &options(), BOMB).warnings.is_empty());
+    }
+
+    #[test]
+    fn long_programs_may_outlast_the_bomb() {
+        let code = [Instruction::MoveForward; 3];
+        let bomb = Duration::from_millis(250);
+        let analysis = analyze(&code, &options(), bomb);
+        assert_eq!(
+            analysis.warnings,
+            vec![Warning::MayOutlastBomb {
+                worst_case: Duration::from_millis(300),
+                bomb,
+            }]
+        );
+        assert!(
+            analyze(&code, &options(), Duration::from_millis(300))
+                .warnings
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn calls_cost_the_subroutine_and_what_follows() {
+        let code = [
+            Instruction::Call(3),
+            Instruction::MoveForward,
+            Instruction::Halt,
+            Instruction::Return,
+        ];
+        let analysis = analyze(&code, &options(), BOMB);
+        assert_eq!(analysis.worst_case_ticks, Some(4));
+    }
+}
@@ -0,0 +1,554 @@
+//! A plain-text assembly format for [`ProgramCode`](super::cpu::ProgramCode),
+//! so programs can be written and diffed outside the game.
+//!
+//! Each line holds at most one instruction, optionally preceded by labels and
+//! followed by a `;` comment:
+//!
+//! ```text
+//! ; Walk until blocked, then turn right.
+//! loop:   jump_if_blocked turn
+//!         move_forward
+//!         jump loop
+//! turn:   if_wall_turn_right
+//!         jump loop
+//! ```
+//!
+//! Jump targets are either labels or slot numbers, registers are written `r0`
+//! to `r3`, and interrupts are `bumped`, `gem_collected` or `bomb_low`.
+//! Conditionals take a sensor and an action, as in `if wall_ahead, turn_right`
+//! or `if gem_ahead, jump grab`. Operands may be separated by spaces or commas.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::{self, Write},
+};
+
+use super::cpu::{Action, Instruction, Interrupt, Register, Sensor};
+
+/// An error in an assembly source, with 1-based line and column numbers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: ParseErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    UnknownMnemonic(String),
+    WrongOperandCount {
+        mnemonic: String,
+        expected: usize,
+        found: usize,
+    },
+    InvalidRegister(String),
+    InvalidInterrupt(String),
+    InvalidSensor(String),
+    InvalidAction(String),
+    InvalidNumber(String),
+    InvalidLabel(String),
+    DuplicateLabel(String),
+    UnknownLabel(String),
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseErrorKind::UnknownMnemonic(mnemonic) => {
+                write!(f, "unknown instruction `{mnemonic}`")
+            }
+            ParseErrorKind::WrongOperandCount {
+                mnemonic,
+                expected,
+                found,
+            } => write!(
+                f,
+                "`{mnemonic}` takes {expected} operand(s), but {found} were given"
+            ),
+            ParseErrorKind::InvalidRegister(register) => {
+                write!(f, "`{register}` is not a register, expected `r0` to `r3`")
+            }
+            ParseErrorKind::InvalidInterrupt(interrupt) => write!(
+                f,
+                "`{interrupt}` is not an interrupt, expected `bumped`, `gem_collected` or `bomb_low`"
+            ),
+            ParseErrorKind::InvalidSensor(sensor) => {
+                write!(f, "`{sensor}` is not a sensor reading")
+            }
+            ParseErrorKind::InvalidAction(action) => write!(
+                f,
+                "`{action}` is not an action, expected `move_forward`, `turn_left`, `turn_right` or `jump`"
+            ),
+            ParseErrorKind::InvalidNumber(number) => write!(f, "`{number}` is not a number"),
+            ParseErrorKind::InvalidLabel(label) => write!(f, "`{label}` is not a valid label"),
+            ParseErrorKind::DuplicateLabel(label) => {
+                write!(f, "label `{label}` is defined more than once")
+            }
+            ParseErrorKind::UnknownLabel(label) => write!(f, "label `{label}` is never defined"),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.kind)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, Copy)]
+struct Token<'a> {
+    text: &'a str,
+    column: usize,
+}
+
+/// An instruction line whose operands haven't been resolved yet.
+#[derive(Debug)]
+struct SourceLine<'a> {
+    line: usize,
+    mnemonic: Token<'a>,
+    operands: Vec<Token<'a>>,
+}
+
+impl SourceLine<'_> {
+    fn error_at(&self, token: Token, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            line: self.line,
+            column: token.column,
+            kind,
+        }
+    }
+}
+
+/// Splits a line into tokens, dropping any comment.
+fn tokenize(line: &str) -> Vec<Token<'_>> {
+    let code = line.split(';').next().unwrap_or_default();
+    let token = |start: usize, end: usize| Token {
+        text: &code[start..end],
+        column: code[..start].chars().count() + 1,
+    };
+
+    let mut tokens = vec![];
+    let mut start = None;
+    for (i, c) in code.char_indices() {
+        if c.is_whitespace() || c == ',' {
+            if let Some(start) = start.take() {
+                tokens.push(token(start, i));
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(start) = start {
+        tokens.push(token(start, code.len()));
+    }
+    tokens
+}
+
+fn is_valid_label(label: &str) -> bool {
+    let mut chars = label.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Parses an assembly source into the instructions of a program.
+pub fn parse_program(source: &str) -> Result<Vec<Instruction>, ParseError> {
+    let mut labels = HashMap::new();
+    let mut lines = vec![];
+
+    for (line_idx, text) in source.lines().enumerate() {
+        let line = line_idx + 1;
+        let mut tokens = tokenize(text).into_iter().peekable();
+
+        while let Some(label) = tokens.next_if(|token| token.text.ends_with(':')) {
+            let name = label.text.trim_end_matches(':');
+            let error = |kind| ParseError {
+                line,
+                column: label.column,
+                kind,
+            };
+            if !is_valid_label(name) {
+                return Err(error(ParseErrorKind::InvalidLabel(name.to_string())));
+            }
+            if labels.insert(name, lines.len()).is_some() {
+                return Err(error(ParseErrorKind::DuplicateLabel(name.to_string())));
+            }
+        }
+
+        if let Some(mnemonic) = tokens.next() {
+            lines.push(SourceLine {
+                line,
+                mnemonic,
+                operands: tokens.collect(),
+            });
+        }
+    }
+
+    lines
+        .iter()
+        .map(|line| parse_instruction(line, &labels))
+        .collect()
+}
+
+fn parse_instruction(
+    line: &SourceLine,
+    labels: &HashMap<&str, usize>,
+) -> Result<Instruction, ParseError> {
+    let mnemonic = line.mnemonic.text.to_ascii_lowercase();
+    let operands = &line.operands;
+    let expect = |expected: usize| {
+        if operands.len() == expected {
+            return Ok(());
+        }
+        let token = operands.get(expected).copied().unwrap_or(line.mnemonic);
+        Err(line.error_at(
+            token,
+            ParseErrorKind::WrongOperandCount {
+                mnemonic: mnemonic.clone(),
+                expected,
+                found: operands.len(),
+            },
+        ))
+    };
+    let register = |token: Token| {
+        Register::ALL
+            .into_iter()
+            .find(|register| token.text.eq_ignore_ascii_case(&register_name(*register)))
+            .ok_or_else(|| line.error_at(token, ParseErrorKind::InvalidRegister(token.text.into())))
+    };
+    let interrupt = |token: Token| {
+        Interrupt::ALL
+            .into_iter()
+            .find(|interrupt| token.text.eq_ignore_ascii_case(interrupt_name(*interrupt)))
+            .ok_or_else(|| {
+                line.error_at(token, ParseErrorKind::InvalidInterrupt(token.text.into()))
+            })
+    };
+    let sensor = |token: Token| {
+        Sensor::ALL
+            .into_iter()
+            .find(|sensor| token.text.eq_ignore_ascii_case(sensor_name(*sensor)))
+            .ok_or_else(|| line.error_at(token, ParseErrorKind::InvalidSensor(token.text.into())))
+    };
+    let number = |token: Token| {
+        token
+            .text
+            .parse::<i32>()
+            .map_err(|_| line.error_at(token, ParseErrorKind::InvalidNumber(token.text.into())))
+    };
+    let target = |token: Token| {
+        if token.text.starts_with(|c: char| c.is_ascii_digit()) {
+            return token.text.parse::<usize>().map_err(|_| {
+                line.error_at(token, ParseErrorKind::InvalidNumber(token.text.into()))
+            });
+        }
+        labels
+            .get(token.text)
+            .copied()
+            .ok_or_else(|| line.error_at(token, ParseErrorKind::UnknownLabel(token.text.into())))
+    };
+
+    let instruction = match mnemonic.as_str() {
+        "move_forward" => {
+            expect(0)?;
+            Instruction::MoveForward
+        }
+        "if_gap_turn_left" => {
+            expect(0)?;
+            Instruction::IfGapTurnLeft
+        }
+        "if_wall_turn_right" => {
+            expect(0)?;
+            Instruction::IfWallTurnRight
+        }
+        "jump" => {
+            expect(1)?;
+            Instruction::Jump(target(operands[0])?)
+        }
+        "jump_if_blocked" => {
+            expect(1)?;
+            Instruction::JumpIfBlocked(target(operands[0])?)
+        }
+        "halt" => {
+            expect(0)?;
+            Instruction::Halt
+        }
+        "set" => {
+            expect(2)?;
+            Instruction::Set(register(operands[0])?, number(operands[1])?)
+        }
+        "inc" => {
+            expect(1)?;
+            Instruction::Inc(register(operands[0])?)
+        }
+        "dec" => {
+            expect(1)?;
+            Instruction::Dec(register(operands[0])?)
+        }
+        "jump_if_zero" => {
+            expect(2)?;
+            Instruction::JumpIfZero(register(operands[0])?, target(operands[1])?)
+        }
+        "call" => {
+            expect(1)?;
+            Instruction::Call(target(operands[0])?)
+        }
+        "return" => {
+            expect(0)?;
+            Instruction::Return
+        }
+        "on_interrupt" => {
+            expect(2)?;
+            Instruction::OnInterrupt(interrupt(operands[0])?, target(operands[1])?)
+        }
+        "detonate" => {
+            expect(0)?;
+            Instruction::Detonate
+        }
+        "if" => {
+            let jumps = operands
+                .get(1)
+                .is_some_and(|action| action.text.eq_ignore_ascii_case("jump"));
+            expect(if jumps { 3 } else { 2 })?;
+            let action = match operands[1].text.to_ascii_lowercase().as_str() {
+                "move_forward" => Action::MoveForward,
+                "turn_left" => Action::TurnLeft,
+                "turn_right" => Action::TurnRight,
+                "jump" => Action::Jump(target(operands[2])?),
+                _ => {
+                    return Err(line.error_at(
+                        operands[1],
+                        ParseErrorKind::InvalidAction(operands[1].text.into()),
+                    ));
+                }
+            };
+            Instruction::If(sensor(operands[0])?, action)
+        }
+        _ => {
+            return Err(line.error_at(
+                line.mnemonic,
+                ParseErrorKind::UnknownMnemonic(line.mnemonic.text.into()),
+            ));
+        }
+    };
+    Ok(instruction)
+}
+
+fn register_name(register: Register) -> String {
+    format!("r{}", register.index())
+}
+
+fn interrupt_name(interrupt: Interrupt) -> &'static str {
+    match interrupt {
+        Interrupt::Bumped => "bumped",
+        Interrupt::GemCollected => "gem_collected",
+        Interrupt::BombLow => "bomb_low",
+    }
+}
+
+fn sensor_name(sensor: Sensor) -> &'static str {
+    match sensor {
+        Sensor::WallAhead => "wall_ahead",
+        Sensor::WallLeft => "wall_left",
+        Sensor::WallRight => "wall_right",
+        Sensor::GemAhead => "gem_ahead",
+        Sensor::FacingNorth => "facing_north",
+        Sensor::FacingEast => "facing_east",
+        Sensor::FacingSouth => "facing_south",
+        Sensor::FacingWest => "facing_west",
+    }
+}
+
+/// Writes a program in the assembly format. Every slot that is jumped to gets
+/// a `slot_N` label, so [`parse_program`] reads the output back unchanged.
+pub fn format_program(code: &[Instruction]) -> String {
+    let targets = code
+        .iter()
+        .filter_map(Instruction::target)
+        .filter(|target| *target < code.len())
+        .collect::<BTreeSet<_>>();
+    let target = |slot: usize| {
+        if targets.contains(&slot) {
+            format!("slot_{slot}")
+        } else {
+            slot.to_string()
+        }
+    };
+
+    let mut source = String::new();
+    for (slot, instruction) in code.iter().enumerate() {
+        if targets.contains(&slot) {
+            writeln!(source, "slot_{slot}:").unwrap();
+        }
+        let line = match *instruction {
+            Instruction::MoveForward => "move_forward".to_string(),
+            Instruction::IfGapTurnLeft => "if_gap_turn_left".to_string(),
+            Instruction::IfWallTurnRight => "if_wall_turn_right".to_string(),
+            Instruction::Jump(slot) => format!("jump {}", target(slot)),
+            Instruction::JumpIfBlocked(slot) => format!("jump_if_blocked {}", target(slot)),
+            Instruction::Halt => "halt".to_string(),
+            Instruction::Set(register, value) => {
+                format!("set {}, {value}", register_name(register))
+            }
+            Instruction::Inc(register) => format!("inc {}", register_name(register)),
+            Instruction::Dec(register) => format!("dec {}", register_name(register)),
+            Instruction::JumpIfZero(register, slot) => {
+                format!("jump_if_zero {}, {}", register_name(register), target(slot))
+            }
+            Instruction::Call(slot) => format!("call {}", target(slot)),
+            Instruction::Return => "return".to_string(),
+            Instruction::OnInterrupt(interrupt, slot) => {
+                format!(
+                    "on_interrupt {}, {}",
+                    interrupt_name(interrupt),
+                    target(slot)
+                )
+            }
+            Instruction::If(sensor, action) => {
+                let action = match action {
+                    Action::MoveForward => "move_forward".to_string(),
+                    Action::TurnLeft => "turn_left".to_string(),
+                    Action::TurnRight => "turn_right".to_string(),
+                    Action::Jump(slot) => format!("jump {}", target(slot)),
+                };
+                format!("if {}, {action}", sensor_name(sensor))
+            }
+            Instruction::Detonate => "detonate".to_string(),
+        };
+        writeln!(source, "    {line}").unwrap();
+    }
+    source
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(source: &str) -> ParseError {
+        parse_program(source).expect_err("source should not parse")
+    }
+
+    #[test]
+    fn formatted_programs_parse_back_unchanged() {
+        let code = vec![
+            Instruction::Set(Register::R1, -3),
+            Instruction::JumpIfBlocked(3),
+            Instruction::MoveForward,
+            Instruction::If(Sensor::GemAhead, Action::Jump(0)),
+            Instruction::JumpIfZero(Register::R1, 8),
+            Instruction::If(Sensor::WallLeft, Action::TurnRight),
+            Instruction::Call(7),
+            Instruction::Return,
+            Instruction::Inc(Register::R3),
+            Instruction::Dec(Register::R2),
+            Instruction::IfGapTurnLeft,
+            Instruction::IfWallTurnRight,
+            Instruction::OnInterrupt(Interrupt::GemCollected, 0),
+            Instruction::Detonate,
+            Instruction::Jump(42),
+            Instruction::Halt,
+        ];
+
+        let source = format_program(&code);
+        let parsed = parse_program(&source).unwrap();
+        assert_eq!(format!("{parsed:?}"), format!("{code:?}"));
+        assert_eq!(format_program(&parsed), source);
+    }
+
+    #[test]
+    fn labels_comments_and_case_are_accepted() {
+        let source = "\
+; Walk until blocked, then turn right.
+loop:   JUMP_IF_BLOCKED turn
+        move_forward   ; keep going
+        jump loop
+turn:   if_wall_turn_right
+        jump 0
+";
+        let code = parse_program(source).unwrap();
+        assert_eq!(code.len(), 5);
+        assert!(matches!(code[0], Instruction::JumpIfBlocked(3)));
+        assert!(matches!(code[2], Instruction::Jump(0)));
+        assert!(matches!(code[3], Instruction::IfWallTurnRight));
+    }
+
+    #[test]
+    fn unknown_mnemonic_points_at_the_mnemonic() {
+        assert_eq!(
+            error("move_forward\nstart:  fly r0"),
+            ParseError {
+                line: 2,
+                column: 9,
+                kind: ParseErrorKind::UnknownMnemonic("fly".into()),
+            }
+        );
+    }
+
+    #[test]
+    fn wrong_operand_count_points_at_the_extra_operand() {
+        assert_eq!(
+            error("halt now"),
+            ParseError {
+                line: 1,
+                column: 6,
+                kind: ParseErrorKind::WrongOperandCount {
+                    mnemonic: "halt".into(),
+                    expected: 0,
+                    found: 1,
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_operands_point_at_the_operand() {
+        assert_eq!(
+            error("set r0, ten").column,
+            9,
+            "number column should follow the comma"
+        );
+        assert_eq!(
+            error("inc r7").kind,
+            ParseErrorKind::InvalidRegister("r7".into())
+        );
+        assert_eq!(
+            error("on_interrupt explode, 0").kind,
+            ParseErrorKind::InvalidInterrupt("explode".into())
+        );
+        assert_eq!(
+            error("if wall_ahead, dance").kind,
+            ParseErrorKind::InvalidAction("dance".into())
+        );
+        assert_eq!(
+            error("if north, turn_left").kind,
+            ParseErrorKind::InvalidSensor("north".into())
+        );
+    }
+
+    #[test]
+    fn label_errors_report_their_position() {
+        assert_eq!(
+            error("a: halt\n  a: halt"),
+            ParseError {
+                line: 2,
+                column: 3,
+                kind: ParseErrorKind::DuplicateLabel("a".into()),
+            }
+        );
+        assert_eq!(
+            error("halt\n\n    jump nowhere"),
+            ParseError {
+                line: 3,
+                column: 10,
+                kind: ParseErrorKind::UnknownLabel("nowhere".into()),
+            }
+        );
+        assert_eq!(
+            error("9lives: halt").kind,
+            ParseErrorKind::InvalidLabel("9lives".into())
+        );
+    }
+}
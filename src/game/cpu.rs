@@ -1,4 +1,4 @@
-use std::time::Duration;
+use std::{collections::HashMap, time::Duration};
 
 use bevy::{color::palettes::css::*, ecs::relationship::RelatedSpawnerCommands, prelude::*};
 use bevy_asset_loader::{
@@ -11,7 +11,7 @@ use bevy_asset_loader::{
 use bevy_ecs_ldtk::{GridCoords, utils::grid_coords_to_translation};
 #[cfg(feature = "dev_native")]
 use bevy_simple_subsecond_system::hot;
-use multimap::MultiMap;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     game::ticks::Tick,
@@ -20,37 +20,80 @@ use crate::{
 };
 
 use super::{
+    analysis::{Warning, analyze},
+    debugger::{Debugger, InstructionItem, SlotLabel, toggle_breakpoint},
+    editor::{
+        DropSlot, MAX_PALETTE_KEY, PaletteInstruction, ProgramEditor, clear_drop_slot,
+        drop_on_slot, highlight_drop_slot,
+    },
+    explosives::{CrateExploded, WallCleared},
+    instructions::{InstructionDefinitions, InstructionIcons},
     level::{CommandParent, ProgramParent, spawn_level_ui},
+    objects::{AllGemsCollected, BombLow, GemCollected, TimeToBomb},
     player::PlayerDirection,
+    rewind::RunHistory,
+    robots::{RobotIndex, SelectedProgram, SelectedRobot},
+    simulation::{Effect, Simulation, StopReason},
+    trace::ExecutionTrace,
 };
 
 pub(super) fn plugin(app: &mut App) {
     app.register_type::<CpuOptions>()
         .register_type::<CpuSpeedDisplay>()
+        .register_type::<RegistersDisplay>()
+        .register_type::<LoopsDisplay>()
+        .register_type::<Register>()
+        .register_type::<Interrupt>()
+        .register_type::<Sensor>()
+        .register_type::<Action>()
+        .register_type::<ConditionalBuilder>()
         .register_type::<ProgramCode>()
         .register_type::<Instruction>()
-        .register_type::<CpuState>();
+        .register_type::<CpuState>()
+        .register_type::<Bumped>()
+        .register_type::<StackOverflow>();
     app.configure_loading_state(
         LoadingStateConfig::new(GameState::Loading).load_collection::<InstructionAssets>(),
     );
     app.add_systems(
         OnEnter(GameState::Playing),
-        (setup_program_code, setup_unlocked_instructions).after(spawn_level_ui),
+        setup_unlocked_instructions.after(spawn_level_ui),
     );
+    app.init_resource::<ConditionalBuilder>();
     app.add_systems(OnExit(GameState::Playing), cleanup_resources);
     app.add_systems(
         FixedUpdate,
         (
             update_cpu_speed_text.run_if(resource_exists_and_changed::<CpuOptions>),
-            update_program_code.run_if(resource_exists_and_changed::<ProgramCode>),
-            update_command_palette.run_if(resource_exists_and_changed::<UnlockedInstructions>),
+            update_registers_text.run_if(
+                resource_exists_and_changed::<SelectedRobot>
+                    .or(any_match_filter::<Changed<CpuState>>),
+            ),
+            update_loops_text.run_if(
+                resource_exists_and_changed::<SelectedRobot>
+                    .or(resource_exists_and_changed::<CpuOptions>)
+                    .or(any_match_filter::<Changed<CpuState>>),
+            ),
+            update_program_code.run_if(
+                resource_exists_and_changed::<SelectedRobot>
+                    .or(any_match_filter::<Changed<ProgramCode>>)
+                    .or(resource_exists_and_changed::<CpuOptions>)
+                    .or(resource_exists_and_changed::<TimeToBomb>)
+                    .or(resource_exists_and_changed::<InstructionDefinitions>),
+            ),
+            update_command_palette.run_if(
+                resource_exists_and_changed::<UnlockedInstructions>
+                    .or(resource_exists_and_changed::<ConditionalBuilder>)
+                    .or(resource_exists_and_changed::<CpuOptions>)
+                    .or(resource_exists_and_changed::<InstructionDefinitions>),
+            ),
         )
             .run_if(in_state(GameState::Playing)),
     );
     app.add_observer(handle_tick)
         .add_observer(handle_instruction)
-        .add_observer(move_forward)
-        .add_observer(if_gap_turn_left);
+        .add_observer(stack_overflow)
+        .add_observer(bomb_low);
 }
 
 #[derive(Resource, Reflect, Debug, Clone, Default)]
@@ -58,6 +101,26 @@ pub(super) fn plugin(app: &mut App) {
 pub struct CpuOptions {
     pub cpu_tick: Duration,
     pub multiplier: f32,
+    /// How many nested [`Instruction::Call`]s fit on the call stack.
+    pub max_call_depth: usize,
+    pub cycle_costs: CycleCosts,
+    /// Whether running off the end of the program starts it over from the
+    /// first slot, instead of ending the run.
+    pub loop_program: bool,
+}
+
+/// How many [`Tick`]s each instruction keeps the CPU busy for, by mnemonic.
+#[derive(Reflect, Debug, Clone, Default, Deref, DerefMut)]
+pub struct CycleCosts(pub HashMap<String, u32>);
+
+impl CycleCosts {
+    /// The cycles `instruction` takes, never less than one.
+    pub fn cost(&self, instruction: &Instruction) -> u32 {
+        self.get(instruction.mnemonic())
+            .copied()
+            .unwrap_or(1)
+            .max(1)
+    }
 }
 
 #[derive(Component, Reflect, Debug, Clone, Copy, Default)]
@@ -72,26 +135,358 @@ fn update_cpu_speed_text(
     }
 }
 
+#[derive(Component, Reflect, Debug, Clone, Copy, Default)]
+pub struct RegistersDisplay;
+
+fn update_registers_text(
+    mut text: Query<&mut Text, With<RegistersDisplay>>,
+    selected: Res<SelectedRobot>,
+    robots: Query<(&RobotIndex, &CpuState)>,
+) {
+    let Some((_, cpu_state)) = robots.iter().find(|(index, _)| index.0 == selected.0) else {
+        return;
+    };
+    for mut text in &mut text {
+        **text = format!("{:?}", cpu_state.registers);
+    }
+}
+
+#[derive(Component, Reflect, Debug, Clone, Copy, Default)]
+pub struct LoopsDisplay;
+
+fn update_loops_text(
+    mut text: Query<&mut Text, With<LoopsDisplay>>,
+    cpu_options: Res<CpuOptions>,
+    selected: Res<SelectedRobot>,
+    robots: Query<(&RobotIndex, &CpuState)>,
+) {
+    let loops = robots
+        .iter()
+        .find(|(index, _)| index.0 == selected.0)
+        .map_or(0, |(_, cpu_state)| cpu_state.loops);
+    for mut text in &mut text {
+        **text = if cpu_options.loop_program {
+            loops.to_string()
+        } else {
+            "off".to_string()
+        };
+    }
+}
+
 #[allow(dead_code)]
-#[derive(Reflect, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Reflect, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum InstructionType {
     Movement,
     Control,
     Scanning,
 }
 
-#[derive(Event, Reflect, Debug, Clone, Copy)]
+impl InstructionType {
+    /// Every category, in the order the palette lists them.
+    pub const ALL: [InstructionType; 3] = [
+        InstructionType::Movement,
+        InstructionType::Control,
+        InstructionType::Scanning,
+    ];
+
+    /// The tint of this category's icons.
+    pub fn color(self) -> Color {
+        match self {
+            InstructionType::Movement => ORANGE.into(),
+            InstructionType::Control => DEEP_SKY_BLUE.into(),
+            InstructionType::Scanning => GOLD.into(),
+        }
+    }
+}
+
+#[derive(Event, Reflect, Serialize, Deserialize, Debug, Clone, Copy)]
 pub enum Instruction {
     MoveForward,
     IfGapTurnLeft,
+    IfWallTurnRight,
+    /// Continue execution at the given slot.
+    Jump(usize),
+    /// Continue execution at the given slot if the cell ahead isn't floor.
+    JumpIfBlocked(usize),
+    /// Stop the program.
+    Halt,
+    /// Store a value in a register.
+    Set(Register, i32),
+    /// Add one to a register.
+    Inc(Register),
+    /// Subtract one from a register.
+    Dec(Register),
+    /// Continue execution at the given slot if the register holds zero.
+    JumpIfZero(Register, usize),
+    /// Push the return address and continue execution at the given slot.
+    Call(usize),
+    /// Continue execution after the most recent [`Instruction::Call`], or
+    /// where an interrupt handler was entered.
+    Return,
+    /// Run the handler at the given slot whenever the interrupt fires.
+    OnInterrupt(Interrupt, usize),
+    /// Carry out the action only if the sensor reads true.
+    If(Sensor, Action),
+    /// Light the fuse of the crate ahead, which explodes on the next tick.
+    Detonate,
+}
+
+/// The largest value a [`Instruction::Set`] can be edited to in the program panel.
+pub const MAX_SET_VALUE: i32 = 99;
+
+pub const REGISTER_COUNT: usize = 4;
+
+#[derive(Reflect, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Register {
+    #[default]
+    R0,
+    R1,
+    R2,
+    R3,
+}
+
+impl Register {
+    pub const ALL: [Register; REGISTER_COUNT] =
+        [Register::R0, Register::R1, Register::R2, Register::R3];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// The register after this one, wrapping around to `R0`.
+    pub fn next(self) -> Self {
+        Self::ALL[(self.index() + 1) % REGISTER_COUNT]
+    }
+}
+
+pub const INTERRUPT_COUNT: usize = 3;
+
+/// World events a program can handle with [`Instruction::OnInterrupt`].
+#[derive(Reflect, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Interrupt {
+    /// The robot tried to move onto a cell it couldn't.
+    #[default]
+    Bumped,
+    /// The robot picked up a gem.
+    GemCollected,
+    /// The bomb has less than a second left.
+    BombLow,
+}
+
+impl Interrupt {
+    pub const ALL: [Interrupt; INTERRUPT_COUNT] = [
+        Interrupt::Bumped,
+        Interrupt::GemCollected,
+        Interrupt::BombLow,
+    ];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// The interrupt after this one, wrapping around to `Bumped`.
+    pub fn next(self) -> Self {
+        Self::ALL[(self.index() + 1) % INTERRUPT_COUNT]
+    }
+}
+
+pub const SENSOR_COUNT: usize = 8;
+
+/// What an [`Instruction::If`] senses before acting.
+#[derive(Reflect, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Sensor {
+    /// The cell ahead isn't floor, or another robot stands on it.
+    #[default]
+    WallAhead,
+    WallLeft,
+    WallRight,
+    /// A gem lies on the cell ahead.
+    GemAhead,
+    FacingNorth,
+    FacingEast,
+    FacingSouth,
+    FacingWest,
+}
+
+impl Sensor {
+    pub const ALL: [Sensor; SENSOR_COUNT] = [
+        Sensor::WallAhead,
+        Sensor::WallLeft,
+        Sensor::WallRight,
+        Sensor::GemAhead,
+        Sensor::FacingNorth,
+        Sensor::FacingEast,
+        Sensor::FacingSouth,
+        Sensor::FacingWest,
+    ];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// The sensor after this one, wrapping around to `WallAhead`.
+    pub fn next(self) -> Self {
+        Self::ALL[(self.index() + 1) % SENSOR_COUNT]
+    }
+}
+
+/// What an [`Instruction::If`] does when its sensor reads true.
+#[derive(Reflect, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Action {
+    #[default]
+    MoveForward,
+    TurnLeft,
+    TurnRight,
+    /// Continue execution at the given slot.
+    Jump(usize),
+}
+
+impl Action {
+    /// The action after this one, wrapping around to `MoveForward`.
+    pub fn next(self) -> Self {
+        match self {
+            Action::MoveForward => Action::TurnLeft,
+            Action::TurnLeft => Action::TurnRight,
+            Action::TurnRight => Action::Jump(0),
+            Action::Jump(_) => Action::MoveForward,
+        }
+    }
 }
 
 impl Instruction {
-    #[allow(dead_code)]
-    pub fn inst_type(&self) -> InstructionType {
+    /// The assembly name of this instruction, which also keys its
+    /// [`InstructionDefinition`](super::instructions::InstructionDefinition).
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Instruction::MoveForward => "move_forward",
+            Instruction::IfGapTurnLeft => "if_gap_turn_left",
+            Instruction::IfWallTurnRight => "if_wall_turn_right",
+            Instruction::Jump(_) => "jump",
+            Instruction::JumpIfBlocked(_) => "jump_if_blocked",
+            Instruction::Halt => "halt",
+            Instruction::Set(..) => "set",
+            Instruction::Inc(_) => "inc",
+            Instruction::Dec(_) => "dec",
+            Instruction::JumpIfZero(..) => "jump_if_zero",
+            Instruction::Call(_) => "call",
+            Instruction::Return => "return",
+            Instruction::OnInterrupt(..) => "on_interrupt",
+            Instruction::If(..) => "if",
+            Instruction::Detonate => "detonate",
+        }
+    }
+
+    /// The slot this instruction jumps to, if it has one.
+    pub fn target(&self) -> Option<usize> {
+        match self {
+            Instruction::Jump(target)
+            | Instruction::JumpIfBlocked(target)
+            | Instruction::JumpIfZero(_, target)
+            | Instruction::Call(target)
+            | Instruction::OnInterrupt(_, target)
+            | Instruction::If(_, Action::Jump(target)) => Some(*target),
+            _ => None,
+        }
+    }
+
+    /// A copy of this instruction jumping to `target` instead.
+    pub fn with_target(self, target: usize) -> Self {
+        match self {
+            Instruction::Jump(_) => Instruction::Jump(target),
+            Instruction::JumpIfBlocked(_) => Instruction::JumpIfBlocked(target),
+            Instruction::JumpIfZero(register, _) => Instruction::JumpIfZero(register, target),
+            Instruction::Call(_) => Instruction::Call(target),
+            Instruction::OnInterrupt(interrupt, _) => Instruction::OnInterrupt(interrupt, target),
+            Instruction::If(sensor, Action::Jump(_)) => {
+                Instruction::If(sensor, Action::Jump(target))
+            }
+            other => other,
+        }
+    }
+
+    /// The register this instruction reads or writes, if it has one.
+    pub fn register(&self) -> Option<Register> {
+        match self {
+            Instruction::Set(register, _)
+            | Instruction::Inc(register)
+            | Instruction::Dec(register)
+            | Instruction::JumpIfZero(register, _) => Some(*register),
+            _ => None,
+        }
+    }
+
+    /// A copy of this instruction using `register` instead.
+    pub fn with_register(self, register: Register) -> Self {
+        match self {
+            Instruction::Set(_, value) => Instruction::Set(register, value),
+            Instruction::Inc(_) => Instruction::Inc(register),
+            Instruction::Dec(_) => Instruction::Dec(register),
+            Instruction::JumpIfZero(_, target) => Instruction::JumpIfZero(register, target),
+            other => other,
+        }
+    }
+
+    /// The interrupt this instruction handles, if it has one.
+    pub fn interrupt(&self) -> Option<Interrupt> {
+        match self {
+            Instruction::OnInterrupt(interrupt, _) => Some(*interrupt),
+            _ => None,
+        }
+    }
+
+    /// A copy of this instruction handling `interrupt` instead.
+    pub fn with_interrupt(self, interrupt: Interrupt) -> Self {
+        match self {
+            Instruction::OnInterrupt(_, target) => Instruction::OnInterrupt(interrupt, target),
+            other => other,
+        }
+    }
+
+    /// The immediate value of this instruction, if it has one.
+    pub fn value(&self) -> Option<i32> {
+        match self {
+            Instruction::Set(_, value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// A copy of this instruction with `value` as its immediate instead.
+    pub fn with_value(self, value: i32) -> Self {
+        match self {
+            Instruction::Set(register, _) => Instruction::Set(register, value),
+            other => other,
+        }
+    }
+
+    /// The sensor this instruction checks, if it has one.
+    pub fn sensor(&self) -> Option<Sensor> {
         match self {
-            Instruction::MoveForward => InstructionType::Movement,
-            Instruction::IfGapTurnLeft => InstructionType::Scanning,
+            Instruction::If(sensor, _) => Some(*sensor),
+            _ => None,
+        }
+    }
+
+    /// A copy of this instruction checking `sensor` instead.
+    pub fn with_sensor(self, sensor: Sensor) -> Self {
+        match self {
+            Instruction::If(_, action) => Instruction::If(sensor, action),
+            other => other,
+        }
+    }
+
+    /// What this instruction does when its sensor reads true, if it has one.
+    pub fn action(&self) -> Option<Action> {
+        match self {
+            Instruction::If(_, action) => Some(*action),
+            _ => None,
+        }
+    }
+
+    /// A copy of this instruction doing `action` instead.
+    pub fn with_action(self, action: Action) -> Self {
+        match self {
+            Instruction::If(sensor, _) => Instruction::If(sensor, action),
+            other => other,
         }
     }
 }
@@ -103,10 +498,25 @@ pub struct InstructionAssets {
     pub movement: Handle<Image>,
     #[asset(path = "images/instructions/control.png")]
     pub control: Handle<Image>,
+    #[asset(path = "images/instructions/scanning.png")]
+    pub scanning: Handle<Image>,
+    #[asset(path = "data/instructions.ron")]
+    pub definitions: Handle<InstructionDefinitions>,
 }
 
-#[derive(Resource, Reflect, Debug, Clone)]
-#[reflect(Resource)]
+impl InstructionAssets {
+    /// The icon of `inst_type`, for instructions without one of their own.
+    pub fn icon(&self, inst_type: InstructionType) -> Handle<Image> {
+        match inst_type {
+            InstructionType::Movement => self.movement.clone(),
+            InstructionType::Control => self.control.clone(),
+            InstructionType::Scanning => self.scanning.clone(),
+        }
+    }
+}
+
+#[derive(Component, Asset, Reflect, Serialize, Deserialize, Debug, Clone)]
+#[reflect(Component)]
 pub struct ProgramCode {
     pub code: Vec<Instruction>,
     pub max_instructions: usize,
@@ -121,100 +531,230 @@ impl Default for ProgramCode {
     }
 }
 
-#[derive(Resource, Reflect, Debug, Clone, Default)]
-#[reflect(Resource)]
+#[derive(Component, Reflect, Debug, Clone, Default)]
+#[reflect(Component)]
 pub struct CpuState {
     pub pc: usize,
+    pub registers: [i32; REGISTER_COUNT],
+    /// Return addresses of the [`Instruction::Call`]s currently executing.
+    pub call_stack: Vec<usize>,
+    /// Ticks left before the instruction at `pc` completes.
+    pub busy_cycles: u32,
+    /// Handler slots registered with [`Instruction::OnInterrupt`], by
+    /// [`Interrupt::index`].
+    pub handlers: [Option<usize>; INTERRUPT_COUNT],
+    /// Interrupts that fired and are waiting for their handler to run.
+    pub pending: Vec<Interrupt>,
+    /// The call stack depth the running handler returns to. Further
+    /// interrupts wait until it does.
+    pub handler_depth: Option<usize>,
+    /// How many times the program wrapped around to the first slot.
+    pub loops: usize,
+}
+
+/// Triggered when a [`Instruction::Call`] would nest deeper than
+/// [`CpuOptions::max_call_depth`].
+#[derive(Event, Reflect, Debug, Clone, Copy)]
+pub struct StackOverflow {
+    pub robot: Entity,
+}
+
+fn stack_overflow(trigger: Trigger<StackOverflow>) {
+    tracing::warn!("Stack overflow in robot {:?}!", trigger.event().robot);
 }
 
 fn handle_tick(
     _: Trigger<Tick>,
     mut commands: Commands,
+    program_state: Res<State<ProgramState>>,
     mut next_state: ResMut<NextState<ProgramState>>,
-    mut cpu_state: ResMut<CpuState>,
-    program_code: Res<ProgramCode>,
+    mut simulation: ResMut<Simulation>,
+    mut debugger: ResMut<Debugger>,
+    mut trace: ResMut<ExecutionTrace>,
+    mut history: ResMut<RunHistory>,
+    cpu_options: Res<CpuOptions>,
+    mut robots: Query<(
+        Entity,
+        &RobotIndex,
+        &mut GridCoords,
+        &mut PlayerDirection,
+        &mut Transform,
+        &mut CpuState,
+    )>,
 ) {
-    if cpu_state.pc < program_code.code.len() {
-        commands.trigger(program_code.code[cpu_state.pc]);
-        cpu_state.pc += 1;
-    } else {
-        tracing::info!("End of program reached.");
-        next_state.set(ProgramState::Buying);
+    // A tick can still arrive after the run ended this frame, since the state
+    // itself only changes next frame.
+    if *program_state.get() != ProgramState::Running || matches!(*next_state, NextState::Pending(_))
+    {
+        return;
+    }
+    let report = simulation.step(&cpu_options);
+    history.record(simulation.snapshot());
+    for coords in report.exploded.iter().copied() {
+        commands.trigger(CrateExploded { coords });
+    }
+    for coords in report.cleared.iter().copied() {
+        commands.trigger(WallCleared { coords });
     }
-}
 
-fn handle_instruction(trigger: Trigger<Instruction>, mut commands: Commands) {
-    tracing::info!("Executing instruction: {:?}", trigger.event());
-    match trigger.event() {
-        Instruction::MoveForward => {
-            commands.trigger(MoveForward);
+    // Mirror what the simulation did onto the spawned robots, in execution order.
+    let mut robots = robots.iter_mut().collect::<Vec<_>>();
+    robots.sort_by_key(|(_, index, ..)| **index);
+    for (robot, index, mut grid_coords, mut direction, mut transform, mut cpu_state) in robots {
+        let (Some(step), Some(state)) =
+            (report.robots.get(index.0), simulation.robots.get(index.0))
+        else {
+            continue;
+        };
+        *cpu_state = state.cpu.clone();
+        if let Some((slot, instruction)) = step.executed {
+            commands.trigger(instruction);
+            trace.record(index.0, slot, instruction, state.robot);
+        }
+
+        for effect in step.effects.iter().copied() {
+            match effect {
+                Effect::Moved { to, .. } => {
+                    *grid_coords = to;
+                    transform.translation =
+                        grid_coords_to_translation(to, IVec2::splat(18)).extend(0.0);
+                }
+                Effect::Turned { direction: turned } => {
+                    *direction = turned;
+                    transform.rotation = turned.rotation();
+                }
+                Effect::Bumped { wall } => {
+                    tracing::info!("Bumped into a wall at {:?}", wall);
+                    commands.trigger(Bumped { robot, wall });
+                }
+                Effect::GemCollected { coords } => {
+                    commands.trigger(GemCollected { coords });
+                }
+                Effect::Interrupted { interrupt } => {
+                    tracing::info!("Robot {} handling {:?}", index.0 + 1, interrupt);
+                }
+                Effect::Detonated { coords } => {
+                    tracing::info!("Robot {} lit the crate at {:?}", index.0 + 1, coords);
+                }
+            }
         }
-        Instruction::IfGapTurnLeft => {
-            commands.trigger(IfGapTurnLeft);
+
+        match step.stopped {
+            Some(StopReason::StackOverflow) => commands.trigger(StackOverflow { robot }),
+            Some(reason) => tracing::info!("Robot {} stopped: {:?}", index.0 + 1, reason),
+            None if step.executed.is_some() && debugger.has_breakpoint(index.0, state.cpu.pc) => {
+                tracing::info!(
+                    "Robot {} hit breakpoint at slot {}",
+                    index.0 + 1,
+                    state.cpu.pc
+                );
+                debugger.paused = true;
+            }
+            None => {}
+        }
+    }
+
+    match report.stopped {
+        None => {}
+        Some(StopReason::AllGemsCollected) => commands.trigger(AllGemsCollected),
+        Some(reason) => {
+            tracing::info!("Program stopped: {:?}", reason);
+            next_state.set(ProgramState::Buying);
         }
     }
 }
 
-#[derive(Event, Reflect, Debug, Clone, Copy)]
-pub struct MoveForward;
+fn bomb_low(_: Trigger<BombLow>, simulation: Option<ResMut<Simulation>>) {
+    if let Some(mut simulation) = simulation {
+        simulation.raise_all(Interrupt::BombLow);
+    }
+}
+
+fn handle_instruction(trigger: Trigger<Instruction>) {
+    tracing::info!("Executing instruction: {:?}", trigger.event());
+}
 
+/// Triggered when a robot tries to move onto a cell that isn't floor.
 #[derive(Event, Reflect, Debug, Clone, Copy)]
-pub struct IfGapTurnLeft;
+pub struct Bumped {
+    pub robot: Entity,
+    pub wall: GridCoords,
+}
 
-fn move_forward(
-    _: Trigger<MoveForward>,
-    mut player: Query<(&mut GridCoords, &mut Transform, &PlayerDirection)>,
-) {
-    for (mut grid_coords, mut transform, direction) in &mut player {
-        *grid_coords += direction.0;
-        transform.translation =
-            grid_coords_to_translation(*grid_coords, IVec2::splat(18)).extend(0.0);
+/// The instructions shown in the palette, in the order they were unlocked.
+#[derive(Resource, Reflect, Debug, Clone, Deref, DerefMut)]
+#[reflect(Resource)]
+pub struct UnlockedInstructions(pub Vec<Instruction>);
+
+impl UnlockedInstructions {
+    /// Whether the kind of `instruction` is unlocked, whatever its operands are.
+    pub fn contains(&self, instruction: &Instruction) -> bool {
+        self.iter()
+            .any(|unlocked| std::mem::discriminant(unlocked) == std::mem::discriminant(instruction))
     }
-}
 
-fn if_gap_turn_left(
-    _: Trigger<IfGapTurnLeft>,
-    mut player: Query<(&mut PlayerDirection, &mut Transform)>,
-) {
-    for (mut direction, mut transform) in &mut player {
-        // Turn left by rotating 90 degrees counter-clockwise
-        direction.0 = GridCoords::new(direction.0.y, -direction.0.x);
-        let angle = Vec2::X.angle_to(Vec2::new(direction.0.x as f32, direction.0.y as f32));
-        transform.rotation = Quat::from_rotation_z(-angle);
+    /// Adds `instruction` to the palette, unless its kind is already there.
+    pub fn unlock(&mut self, instruction: Instruction) {
+        if !self.contains(&instruction) {
+            self.push(instruction);
+        }
     }
-}
 
-#[derive(Resource, Reflect, Debug, Clone, Deref, DerefMut)]
-#[reflect(Resource, opaque)]
-pub struct UnlockedInstructions(pub MultiMap<InstructionType, Instruction>);
+    /// The palette entries of `inst_type`, with conditionals as currently
+    /// assembled in the `builder`.
+    pub fn in_palette(
+        &self,
+        inst_type: InstructionType,
+        builder: &ConditionalBuilder,
+        definitions: &InstructionDefinitions,
+    ) -> Vec<Instruction> {
+        self.iter()
+            .copied()
+            .map(|instruction| match instruction {
+                Instruction::If(..) => builder.instruction(),
+                other => other,
+            })
+            .filter(|instruction| definitions.category(instruction) == inst_type)
+            .collect()
+    }
+}
 
 impl Default for UnlockedInstructions {
     fn default() -> Self {
-        UnlockedInstructions(
-            multimap::multimap!( InstructionType::Movement => Instruction::MoveForward ),
-        )
+        UnlockedInstructions(vec![Instruction::MoveForward])
     }
 }
 
-#[cfg_attr(feature = "dev_native", hot(rerun_on_hot_reload = true))]
-fn setup_unlocked_instructions(mut commands: Commands) {
-    commands.init_resource::<UnlockedInstructions>();
+/// The [`Instruction::If`] being assembled in the palette.
+#[derive(Resource, Reflect, Debug, Clone, Copy, Default)]
+#[reflect(Resource)]
+pub struct ConditionalBuilder {
+    pub sensor: Sensor,
+    pub action: Action,
+}
+
+impl ConditionalBuilder {
+    pub fn instruction(&self) -> Instruction {
+        Instruction::If(self.sensor, self.action)
+    }
+}
+
+fn next_builder_sensor(_: Trigger<Pointer<Click>>, mut builder: ResMut<ConditionalBuilder>) {
+    builder.sensor = builder.sensor.next();
+}
+
+fn next_builder_action(_: Trigger<Pointer<Click>>, mut builder: ResMut<ConditionalBuilder>) {
+    builder.action = builder.action.next();
 }
 
 #[cfg_attr(feature = "dev_native", hot(rerun_on_hot_reload = true))]
-fn setup_program_code(mut commands: Commands) {
-    tracing::info!("Setting up program code...");
-    let program_code = ProgramCode {
-        code: vec![Instruction::MoveForward],
-        max_instructions: 1,
-    };
-    commands.insert_resource(program_code.clone());
+fn setup_unlocked_instructions(mut commands: Commands) {
+    commands.init_resource::<UnlockedInstructions>();
 }
 
 fn cleanup_resources(mut commands: Commands) {
-    commands.remove_resource::<ProgramCode>();
     commands.remove_resource::<CpuOptions>();
-    commands.remove_resource::<CpuState>();
+    commands.remove_resource::<Simulation>();
     commands.remove_resource::<UnlockedInstructions>();
 }
 
@@ -224,6 +764,10 @@ fn update_command_palette(
     parent: Single<Entity, With<CommandParent>>,
     children: Query<&Children>,
     unlocked_instructions: Res<UnlockedInstructions>,
+    conditional_builder: Res<ConditionalBuilder>,
+    cpu_options: Res<CpuOptions>,
+    definitions: Res<InstructionDefinitions>,
+    icons: InstructionIcons,
 ) {
     let parent = *parent;
     for child in children.get(parent).unwrap().iter() {
@@ -231,15 +775,24 @@ fn update_command_palette(
     }
 
     commands.entity(parent).with_children(|parent| {
-        for inst_type in [
-            InstructionType::Movement,
-            InstructionType::Control,
-            InstructionType::Scanning,
-        ] {
-            if let Some(instructions) = unlocked_instructions.get_vec(&inst_type) {
-                spawn_instruction_group(parent, inst_type, instructions);
-            } else {
+        // Palette entries are numbered for the editor's number keys.
+        let mut first_key = 1;
+        for inst_type in InstructionType::ALL {
+            let instructions =
+                unlocked_instructions.in_palette(inst_type, &conditional_builder, &definitions);
+            if instructions.is_empty() {
                 tracing::warn!("No instructions found for type: {:?}", inst_type);
+            } else {
+                spawn_instruction_group(
+                    parent,
+                    inst_type,
+                    &instructions,
+                    first_key,
+                    &definitions,
+                    &icons,
+                    &cpu_options.cycle_costs,
+                );
+                first_key += instructions.len();
             }
         }
     });
@@ -249,6 +802,10 @@ fn spawn_instruction_group(
     parent: &mut RelatedSpawnerCommands<ChildOf>,
     inst_type: InstructionType,
     instructions: &[Instruction],
+    first_key: usize,
+    definitions: &InstructionDefinitions,
+    icons: &InstructionIcons,
+    cycle_costs: &CycleCosts,
 ) {
     tracing::info!("Spawning instruction group: {:?}", inst_type);
     parent
@@ -267,30 +824,69 @@ fn spawn_instruction_group(
             TextColor(MEDIUM_AQUAMARINE.into()),
         ))
         .with_children(|parent| {
-            for instruction in instructions.iter().copied() {
-                parent.spawn((
-                    Name::new(format!("Instruction: {instruction:?}")),
-                    widget::ui_row(children![
-                        (
-                            Node {
-                                width: Val::Percent(100.0),
-                                margin: UiRect::all(Val::Px(5.0)),
-                                ..default()
+            for (key, instruction) in (first_key..).zip(instructions.iter().copied()) {
+                let name = definitions.name(&instruction);
+                let cycles = cycle_costs.cost(&instruction);
+                let description = definitions
+                    .get(&instruction)
+                    .map_or("", |definition| &definition.description);
+                let mut title = match (instruction.sensor(), instruction.action()) {
+                    (Some(sensor), Some(action)) => {
+                        format!("{name} {sensor:?} then {action:?} ({cycles} cycles)")
+                    }
+                    _ => format!("{name} ({cycles} cycles)"),
+                };
+                if key <= MAX_PALETTE_KEY {
+                    title = format!("[{key}] {title}");
+                }
+                parent
+                    .spawn((
+                        Name::new(format!("Instruction: {instruction:?}")),
+                        widget::ui_row(children![
+                            icons.tile(&instruction),
+                            (
+                                Node {
+                                    flex_direction: FlexDirection::Column,
+                                    width: Val::Percent(100.0),
+                                    margin: UiRect::all(Val::Px(5.0)),
+                                    ..default()
+                                },
+                                Pickable::IGNORE,
+                                children![
+                                    (
+                                        Text::new(title),
+                                        TextFont::from_font_size(18.0),
+                                        TextColor(BLANCHED_ALMOND.into()),
+                                    ),
+                                    (
+                                        Text::new(description),
+                                        TextFont::from_font_size(14.0),
+                                        TextColor(LIGHT_GRAY.into()),
+                                    ),
+                                ],
+                            ),
+                        ]),
+                    ))
+                    .with_children(|parent| {
+                        if instruction.sensor().is_some() {
+                            parent.spawn(widget::button_small("S", next_builder_sensor));
+                            parent.spawn(widget::button_small("A", next_builder_action));
+                        }
+                        parent.spawn(widget::button_small(
+                            "+",
+                            move |_: Trigger<Pointer<Click>>, mut editor: ProgramEditor| {
+                                tracing::info!("Adding instruction: {:?}", instruction);
+                                let Some(end) =
+                                    editor.get().map(|program_code| program_code.code.len())
+                                else {
+                                    return;
+                                };
+                                editor.insert(end, instruction);
                             },
-                            Text::new(format!("{instruction:?}")),
-                            TextFont::from_font_size(18.0),
-                            TextColor(BLANCHED_ALMOND.into()),
-                        ),
-                        widget::button_small("+", move |_: Trigger<Pointer<Click>>, mut program_code: ResMut<ProgramCode>| {
-                            tracing::info!("Adding instruction: {:?}", instruction);
-                            if program_code.code.len() < program_code.max_instructions {
-                                program_code.code.push(instruction);
-                            } else {
-                                tracing::warn!("Maximum instruction limit reached: {}", program_code.max_instructions);
-                            }
-                        }),
-                    ]),
-                ));
+                        ));
+                    })
+                    // Rows ignore the pointer by default, but this one is dragged.
+                    .insert((PaletteInstruction(instruction), Pickable::default()));
             }
         });
 }
@@ -299,39 +895,165 @@ fn spawn_instruction_item(
     parent: &mut RelatedSpawnerCommands<ChildOf>,
     idx: usize,
     instruction: Instruction,
+    icon: impl Bundle,
+    warning: Option<&Warning>,
 ) {
     tracing::info!("Spawning instruction item: {:?}", instruction);
-    parent.spawn((
-        Name::new(format!("Instruction: {instruction:?}")),
-        Node {
-            flex_direction: FlexDirection::Row,
-            grid_column: GridPlacement::start(2),
-            grid_row: GridPlacement::start(idx as i16 + 1),
-            justify_content: JustifyContent::SpaceBetween,
-            width: Val::Percent(100.0),
-            margin: UiRect::all(Val::Px(3.0)),
-            ..default()
+    parent
+        .spawn((
+            Name::new(format!("Instruction: {instruction:?}")),
+            InstructionItem(idx),
+            DropSlot(idx),
+            BackgroundColor(Color::NONE),
+            Node {
+                flex_direction: FlexDirection::Row,
+                grid_column: GridPlacement::start(2),
+                grid_row: GridPlacement::start(idx as i16 + 1),
+                justify_content: JustifyContent::SpaceBetween,
+                width: Val::Percent(100.0),
+                margin: UiRect::all(Val::Px(3.0)),
+                ..default()
+            },
+            children![(
+                Node {
+                    flex_direction: FlexDirection::Row,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                Pickable::IGNORE,
+                children![
+                    icon,
+                    (
+                        Text::new(format!("{instruction:?}")),
+                        TextFont::from_font_size(18.0),
+                        TextColor(if warning.is_some() {
+                            ORANGE.into()
+                        } else {
+                            BLANCHED_ALMOND.into()
+                        }),
+                    ),
+                ],
+            )],
+        ))
+        .with_children(|parent| {
+            if let Some(warning) = warning {
+                parent.spawn((
+                    Name::new("Instruction Warning"),
+                    Text::new(format!("! {warning}")),
+                    TextFont::from_font_size(14.0),
+                    TextColor(ORANGE.into()),
+                    Pickable::IGNORE,
+                ));
+            }
+            parent
+                .spawn((
+                    Name::new("Instruction Controls"),
+                    Node {
+                        flex_direction: FlexDirection::Row,
+                        ..default()
+                    },
+                    Pickable::IGNORE,
+                ))
+                .with_children(|parent| {
+                    if instruction.sensor().is_some() {
+                        parent.spawn(operand_button("S", idx, |instruction, _| {
+                            instruction.sensor().map_or(instruction, |sensor| {
+                                instruction.with_sensor(sensor.next())
+                            })
+                        }));
+                        parent.spawn(operand_button("A", idx, |instruction, _| {
+                            instruction.action().map_or(instruction, |action| {
+                                instruction.with_action(action.next())
+                            })
+                        }));
+                    }
+                    if instruction.interrupt().is_some() {
+                        parent.spawn(operand_button("I", idx, |instruction, _| {
+                            instruction.interrupt().map_or(instruction, |interrupt| {
+                                instruction.with_interrupt(interrupt.next())
+                            })
+                        }));
+                    }
+                    if instruction.register().is_some() {
+                        parent.spawn(operand_button("R", idx, |instruction, _| {
+                            instruction.register().map_or(instruction, |register| {
+                                instruction.with_register(register.next())
+                            })
+                        }));
+                    }
+                    if instruction.value().is_some() {
+                        parent.spawn(operand_button("v", idx, |instruction, _| {
+                            instruction.value().map_or(instruction, |value| {
+                                instruction.with_value((value - 1).max(0))
+                            })
+                        }));
+                        parent.spawn(operand_button("^", idx, |instruction, _| {
+                            instruction.value().map_or(instruction, |value| {
+                                instruction.with_value((value + 1).min(MAX_SET_VALUE))
+                            })
+                        }));
+                    }
+                    if instruction.target().is_some() {
+                        // A program without slots has nowhere to jump to.
+                        parent.spawn(operand_button("<", idx, |instruction, slots| {
+                            instruction
+                                .target()
+                                .and_then(|target| (target + slots).checked_sub(1))
+                                .and_then(|target| target.checked_rem(slots))
+                                .map_or(instruction, |target| instruction.with_target(target))
+                        }));
+                        parent.spawn(operand_button(">", idx, |instruction, slots| {
+                            instruction
+                                .target()
+                                .and_then(|target| (target + 1).checked_rem(slots))
+                                .map_or(instruction, |target| instruction.with_target(target))
+                        }));
+                    }
+                    parent.spawn(widget::button_small(
+                        "-",
+                        move |_: Trigger<Pointer<Click>>, mut editor: ProgramEditor| {
+                            tracing::info!("Removing instruction: {:?}", instruction);
+                            editor.remove(idx);
+                        },
+                    ));
+                });
+        })
+        .observe(drop_on_slot)
+        .observe(highlight_drop_slot)
+        .observe(clear_drop_slot);
+}
+
+/// A button that rewrites an operand of the instruction at `idx`. `edit` is
+/// given the current instruction and the number of program slots.
+fn operand_button(
+    text: &'static str,
+    idx: usize,
+    edit: impl Fn(Instruction, usize) -> Instruction + Send + Sync + 'static,
+) -> impl Bundle {
+    widget::button_small(
+        text,
+        move |_: Trigger<Pointer<Click>>, mut editor: ProgramEditor| {
+            let Some(program_code) = editor.get() else {
+                return;
+            };
+            // The panel is only rebuilt on the next fixed update, so the slot
+            // may already be gone.
+            let Some(instruction) = program_code.code.get(idx).copied() else {
+                return;
+            };
+            let edited = edit(instruction, program_code.max_instructions);
+            tracing::info!("Editing {:?} into {:?}", instruction, edited);
+            editor.change(idx, edited);
         },
-        children![
-            (
-                Text::new(format!("{instruction:?}")),
-                TextFont::from_font_size(18.0),
-                TextColor(BLANCHED_ALMOND.into()),
-            ),
-            widget::button_small(
-                "-",
-                move |_: Trigger<Pointer<Click>>, mut program_code: ResMut<ProgramCode>| {
-                    tracing::info!("Removing instruction: {:?}", instruction);
-                    program_code.code.remove(idx);
-                }
-            )
-        ],
-    ));
+    )
 }
 
 #[cfg_attr(feature = "dev_native", hot)]
 fn update_program_code(
-    program_code: Res<ProgramCode>,
+    program: SelectedProgram,
+    cpu_options: Res<CpuOptions>,
+    time_to_bomb: Res<TimeToBomb>,
+    icons: InstructionIcons,
     parent: Single<Entity, With<ProgramParent>>,
     children: Query<&Children>,
     mut commands: Commands,
@@ -340,28 +1062,85 @@ fn update_program_code(
     for child in children.get(parent).unwrap().iter() {
         commands.entity(child).despawn();
     }
+    let Some(program_code) = program.get() else {
+        return;
+    };
+    let analysis = analyze(&program_code.code, &cpu_options, time_to_bomb.duration);
 
     commands.entity(parent).with_children(|parent| {
         for i in 0..program_code.max_instructions {
+            parent
+                .spawn((
+                    Name::new(format!("Instruction Slot {i}")),
+                    Node {
+                        grid_column: GridPlacement::start(1),
+                        grid_row: GridPlacement::start(i as i16 + 1),
+                        width: Val::Auto,
+                        height: Val::Auto,
+                        margin: UiRect::all(Val::Px(3.0)),
+                        ..default()
+                    },
+                    Text::new(format!("{i}")),
+                    TextFont::from_font_size(18.0),
+                    TextColor(MEDIUM_SPRING_GREEN.into()),
+                    SlotLabel(i),
+                    DropSlot(i),
+                ))
+                .observe(toggle_breakpoint)
+                .observe(drop_on_slot)
+                .observe(highlight_drop_slot)
+                .observe(clear_drop_slot);
+        }
+
+        // Empty slots are drop targets too
+        for i in program_code.code.len()..program_code.max_instructions {
+            parent
+                .spawn((
+                    Name::new(format!("Empty Slot {i}")),
+                    Node {
+                        grid_column: GridPlacement::start(2),
+                        grid_row: GridPlacement::start(i as i16 + 1),
+                        width: Val::Percent(100.0),
+                        min_height: Val::Px(30.0),
+                        margin: UiRect::all(Val::Px(3.0)),
+                        ..default()
+                    },
+                    Text::new("empty"),
+                    TextFont::from_font_size(14.0),
+                    TextColor(DIM_GRAY.into()),
+                    DropSlot(i),
+                ))
+                .observe(drop_on_slot)
+                .observe(highlight_drop_slot)
+                .observe(clear_drop_slot);
+        }
+
+        // Spawn the instruction items
+        for (i, instruction) in program_code.code.iter().copied().enumerate() {
+            spawn_instruction_item(
+                parent,
+                i,
+                instruction,
+                icons.tile(&instruction),
+                analysis.warning_for(i),
+            );
+        }
+
+        // Warnings about the program as a whole go below the last slot
+        let warnings = analysis.warnings.iter().filter(|w| w.slot().is_none());
+        for (i, warning) in warnings.enumerate() {
             parent.spawn((
-                Name::new(format!("Instruction Slot {i}")),
+                Name::new("Program Warning"),
                 Node {
-                    grid_column: GridPlacement::start(1),
-                    grid_row: GridPlacement::start(i as i16 + 1),
-                    width: Val::Auto,
-                    height: Val::Auto,
+                    grid_column: GridPlacement::start_span(1, 2),
+                    grid_row: GridPlacement::start((program_code.max_instructions + i) as i16 + 1),
                     margin: UiRect::all(Val::Px(3.0)),
                     ..default()
                 },
-                Text::new(format!("{i}")),
-                TextFont::from_font_size(18.0),
-                TextColor(MEDIUM_SPRING_GREEN.into()),
+                Text::new(format!("! {warning}")),
+                TextFont::from_font_size(14.0),
+                TextColor(ORANGE.into()),
             ));
         }
-
-        // Spawn the instruction items
-        for (i, instruction) in program_code.code.iter().copied().enumerate() {
-            spawn_instruction_item(parent, i, instruction);
-        }
     });
 }
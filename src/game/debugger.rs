@@ -0,0 +1,233 @@
+//! Pausing, single-stepping and breakpoints for a running program.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::Duration,
+};
+
+use bevy::{color::palettes::css::*, prelude::*};
+
+use crate::{
+    game::ticks::Tick,
+    state::{GameState, ProgramState},
+    theme::interaction::Inactive,
+};
+
+use super::{
+    cpu::{CpuOptions, CpuState, ProgramCode},
+    objects::{BombTimer, LevelCompleted, advance_bomb_timer},
+    rewind::RunHistory,
+    robots::{RobotIndex, SelectedRobot},
+};
+
+pub(super) fn plugin(app: &mut App) {
+    app.register_type::<Debugger>()
+        .register_type::<DebugButton>()
+        .register_type::<SlotLabel>()
+        .register_type::<InstructionItem>();
+    app.init_resource::<Debugger>();
+    app.add_systems(OnEnter(GameState::Playing), clear_breakpoints);
+    app.add_systems(OnEnter(ProgramState::Running), resume_on_run);
+    app.add_observer(forget_level_breakpoints);
+    app.add_systems(
+        Update,
+        (
+            update_breakpoint_markers.run_if(
+                resource_exists_and_changed::<Debugger>
+                    .or(resource_exists_and_changed::<SelectedRobot>)
+                    .or(any_match_filter::<Added<SlotLabel>>),
+            ),
+            highlight_pc.run_if(
+                resource_exists_and_changed::<SelectedRobot>
+                    .or(resource_exists_and_changed::<RunHistory>)
+                    .or(state_changed::<ProgramState>)
+                    .or(any_match_filter::<Changed<CpuState>>)
+                    .or(any_match_filter::<Added<InstructionItem>>),
+            ),
+        )
+            .run_if(in_state(GameState::Playing)),
+    );
+}
+
+#[derive(Resource, Reflect, Debug, Clone, Default)]
+#[reflect(Resource)]
+pub struct Debugger {
+    /// Whether [`Tick`]s are held back while a program runs.
+    pub paused: bool,
+    /// Slots that pause the program when their robot reaches them, by
+    /// [`RobotIndex`].
+    pub breakpoints: BTreeMap<usize, BTreeSet<usize>>,
+}
+
+impl Debugger {
+    /// Whether the robot at `robot` pauses on reaching `slot`.
+    pub fn has_breakpoint(&self, robot: usize, slot: usize) -> bool {
+        self.breakpoints
+            .get(&robot)
+            .is_some_and(|slots| slots.contains(&slot))
+    }
+
+    /// Sets or removes the breakpoint of the robot at `robot` on `slot`.
+    pub fn toggle(&mut self, robot: usize, slot: usize) {
+        let slots = self.breakpoints.entry(robot).or_default();
+        if !slots.remove(&slot) {
+            slots.insert(slot);
+        }
+    }
+}
+
+/// Run condition for everything that should stand still while paused.
+pub fn program_paused(debugger: Res<Debugger>) -> bool {
+    debugger.paused
+}
+
+/// Marks the Pause/Step/Continue buttons, which only work while running.
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+pub struct DebugButton;
+
+/// The slot number label of a program slot. Clicking it toggles a breakpoint.
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+pub struct SlotLabel(pub usize);
+
+/// The instruction shown in a program slot.
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+pub struct InstructionItem(pub usize);
+
+fn resume_on_run(mut debugger: ResMut<Debugger>) {
+    debugger.paused = false;
+}
+
+pub fn pause_program(
+    trigger: Trigger<Pointer<Click>>,
+    inactive: Query<Has<Inactive>>,
+    mut debugger: ResMut<Debugger>,
+) {
+    if inactive.get(trigger.target()).unwrap_or_default() {
+        return;
+    }
+    tracing::info!("Pausing program");
+    debugger.paused = true;
+}
+
+pub fn continue_program(
+    trigger: Trigger<Pointer<Click>>,
+    inactive: Query<Has<Inactive>>,
+    mut debugger: ResMut<Debugger>,
+) {
+    if inactive.get(trigger.target()).unwrap_or_default() {
+        return;
+    }
+    tracing::info!("Continuing program");
+    debugger.paused = false;
+}
+
+/// Pauses the program and runs exactly one instruction of the selected robot,
+/// however many cycles it takes. The bomb keeps counting down for every tick
+/// stepped through, since its timer is paused too.
+pub fn step_program(
+    trigger: Trigger<Pointer<Click>>,
+    mut commands: Commands,
+    inactive: Query<Has<Inactive>>,
+    mut debugger: ResMut<Debugger>,
+    selected: Res<SelectedRobot>,
+    robots: Query<(&RobotIndex, &CpuState, &ProgramCode)>,
+    cpu_options: Res<CpuOptions>,
+) {
+    if inactive.get(trigger.target()).unwrap_or_default() {
+        return;
+    }
+    let Some((_, cpu_state, program_code)) =
+        robots.iter().find(|(index, ..)| index.0 == selected.0)
+    else {
+        return;
+    };
+    debugger.paused = true;
+    let ticks = match cpu_state.busy_cycles {
+        0 => program_code
+            .code
+            .get(cpu_state.pc)
+            .map_or(1, |instruction| cpu_options.cycle_costs.cost(instruction)),
+        busy_cycles => busy_cycles,
+    };
+    tracing::info!("Stepping {} tick(s)", ticks);
+    let tick = cpu_options.cpu_tick.mul_f32(cpu_options.multiplier);
+    for _ in 0..ticks {
+        commands.run_system_cached_with(step_tick, tick);
+    }
+}
+
+/// Runs one stepped tick, unless an earlier one already ended the run.
+fn step_tick(
+    In(tick): In<Duration>,
+    mut commands: Commands,
+    next_state: Res<NextState<ProgramState>>,
+    mut bomb_timer: Option<ResMut<BombTimer>>,
+) {
+    if matches!(*next_state, NextState::Pending(_)) {
+        return;
+    }
+    commands.trigger(Tick);
+    if let Some(bomb_timer) = bomb_timer.as_deref_mut() {
+        advance_bomb_timer(&mut commands, bomb_timer, tick);
+    }
+}
+
+pub fn toggle_breakpoint(
+    trigger: Trigger<Pointer<Click>>,
+    slots: Query<&SlotLabel>,
+    selected: Res<SelectedRobot>,
+    mut debugger: ResMut<Debugger>,
+) {
+    let Ok(SlotLabel(slot)) = slots.get(trigger.target()) else {
+        return;
+    };
+    debugger.toggle(selected.0, *slot);
+    tracing::info!("Breakpoints: {:?}", debugger.breakpoints);
+}
+
+fn clear_breakpoints(mut debugger: ResMut<Debugger>) {
+    debugger.breakpoints.clear();
+}
+
+/// The next level has other programs, so their slots mean something else.
+fn forget_level_breakpoints(_: Trigger<LevelCompleted>, mut debugger: ResMut<Debugger>) {
+    debugger.breakpoints.clear();
+}
+
+fn update_breakpoint_markers(
+    debugger: Res<Debugger>,
+    selected: Res<SelectedRobot>,
+    mut labels: Query<(&SlotLabel, &mut TextColor)>,
+) {
+    for (SlotLabel(slot), mut color) in &mut labels {
+        color.0 = if debugger.has_breakpoint(selected.0, *slot) {
+            CRIMSON.into()
+        } else {
+            MEDIUM_SPRING_GREEN.into()
+        };
+    }
+}
+
+/// Highlights the selected robot's next instruction while it runs, or while
+/// rewinding through its run.
+fn highlight_pc(
+    selected: Res<SelectedRobot>,
+    program_state: Res<State<ProgramState>>,
+    history: Res<RunHistory>,
+    robots: Query<(&RobotIndex, &CpuState)>,
+    mut items: Query<(&InstructionItem, &mut BackgroundColor)>,
+) {
+    let showing_run = *program_state.get() == ProgramState::Running || history.cursor.is_some();
+    let pc = robots
+        .iter()
+        .find(|(index, _)| index.0 == selected.0)
+        .map(|(_, cpu_state)| cpu_state.pc)
+        .filter(|_| showing_run);
+    for (InstructionItem(slot), mut background) in &mut items {
+        background.0 = if pc == Some(*slot) {
+            DARK_SLATE_BLUE.into()
+        } else {
+            Color::NONE
+        };
+    }
+}
@@ -0,0 +1,717 @@
+//! Editing the selected robot's program in place: dragging instructions
+//! between slots, and from the command palette onto a slot, or from the
+//! keyboard at the [`EditorCursor`].
+//!
+//! Moving, inserting or removing an instruction shifts the slots after it, so
+//! every jump target is remapped to keep pointing at the same instruction.
+//!
+//! Every change to a program's code goes through the [`ProgramEditor`], which
+//! keeps it as a [`ProgramEdit`] in the [`EditHistory`] so it can be undone and
+//! redone. That includes operand changes, loading a saved program and the
+//! solver's hint, since undoing slot edits against a program that changed
+//! behind the history's back would scramble it.
+
+use std::collections::VecDeque;
+
+use bevy::{color::palettes::css::*, ecs::system::SystemParam, prelude::*};
+use bevy_enhanced_input::{
+    events::Fired,
+    prelude::{
+        Actions, Binding, InputAction, InputContext, InputContextAppExt, InputModKeys, ModKeys,
+        Press,
+    },
+};
+
+use crate::state::GameState;
+
+use super::{
+    cpu::{ConditionalBuilder, Instruction, InstructionType, ProgramCode, UnlockedInstructions},
+    debugger::{InstructionItem, SlotLabel},
+    instructions::InstructionDefinitions,
+    objects::LevelCompleted,
+    robots::{RobotIndex, SelectedProgram, SelectedRobot},
+};
+
+/// The number keys that insert palette entries, the first one inserting the
+/// first entry.
+const PALETTE_KEYS: [KeyCode; MAX_PALETTE_KEY] = [
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
+
+/// How many palette entries have a number key.
+pub const MAX_PALETTE_KEY: usize = 9;
+
+/// How many edits can be undone.
+const HISTORY_SIZE: usize = 100;
+
+pub(super) fn plugin(app: &mut App) {
+    app.register_type::<PaletteInstruction>()
+        .register_type::<DropSlot>()
+        .register_type::<EditorCursor>()
+        .register_type::<ProgramEdit>()
+        .register_type::<EditHistory>()
+        .register_type::<ProgramEditorContext>();
+    app.init_resource::<EditorCursor>()
+        .init_resource::<EditHistory>();
+    app.add_input_context::<ProgramEditorContext>();
+    app.add_systems(
+        OnEnter(GameState::Playing),
+        (spawn_editor_context, clear_history),
+    );
+    app.add_systems(
+        Update,
+        highlight_cursor
+            .run_if(
+                resource_exists_and_changed::<EditorCursor>
+                    .or(resource_exists_and_changed::<SelectedRobot>)
+                    .or(any_match_filter::<Added<SlotLabel>>),
+            )
+            .run_if(in_state(GameState::Playing)),
+    );
+    app.add_observer(editor_binding)
+        .add_observer(cursor_up)
+        .add_observer(cursor_down)
+        .add_observer(insert_from_palette)
+        .add_observer(delete_at_cursor)
+        .add_observer(move_up)
+        .add_observer(move_down)
+        .add_observer(undo_edit::<Fired<UndoEdit>>)
+        .add_observer(redo_edit::<Fired<RedoEdit>>)
+        .add_observer(forget_level_history);
+}
+
+/// A palette entry that can be dragged onto a program slot.
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+pub struct PaletteInstruction(pub Instruction);
+
+/// A program slot that instructions can be dropped onto.
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+pub struct DropSlot(pub usize);
+
+/// Remaps the jump targets of `code` with `remap`, which is given each target.
+fn remap_targets(code: &mut [Instruction], remap: impl Fn(usize) -> usize) {
+    for instruction in code {
+        if let Some(target) = instruction.target() {
+            *instruction = instruction.with_target(remap(target));
+        }
+    }
+}
+
+/// Inserts `instruction` at `slot`, or at the end if `slot` is past it.
+/// Returns the slot it ended up in, or `None` if the program is full.
+fn insert_instruction(
+    program_code: &mut ProgramCode,
+    slot: usize,
+    instruction: Instruction,
+) -> Option<usize> {
+    if program_code.code.len() >= program_code.max_instructions {
+        tracing::warn!(
+            "Maximum instruction limit reached: {}",
+            program_code.max_instructions
+        );
+        return None;
+    }
+    let slot = slot.min(program_code.code.len());
+    remap_targets(&mut program_code.code, |target| {
+        if target >= slot { target + 1 } else { target }
+    });
+    program_code.code.insert(slot, instruction);
+    Some(slot)
+}
+
+/// Removes the instruction at `slot`. Jumps to it now go to the instruction
+/// that took its place.
+fn remove_instruction(program_code: &mut ProgramCode, slot: usize) -> Option<Instruction> {
+    if slot >= program_code.code.len() {
+        return None;
+    }
+    let instruction = program_code.code.remove(slot);
+    remap_targets(&mut program_code.code, |target| {
+        if target > slot { target - 1 } else { target }
+    });
+    Some(instruction)
+}
+
+/// Moves the instruction at `from` to `to`, or to the end if `to` is past it.
+/// Returns the slot it ended up in.
+fn move_instruction(program_code: &mut ProgramCode, from: usize, to: usize) -> Option<usize> {
+    if from >= program_code.code.len() {
+        return None;
+    }
+    let to = to.min(program_code.code.len() - 1);
+    let instruction = program_code.code.remove(from);
+    program_code.code.insert(to, instruction);
+    remap_targets(&mut program_code.code, |target| match target {
+        target if target == from => to,
+        target if from < target && target <= to => target - 1,
+        target if to <= target && target < from => target + 1,
+        target => target,
+    });
+    Some(to)
+}
+
+/// An edit to a program, with what it takes to undo it.
+#[derive(Reflect, Debug, Clone)]
+pub enum ProgramEdit {
+    Insert {
+        slot: usize,
+        instruction: Instruction,
+    },
+    Remove {
+        slot: usize,
+        instruction: Instruction,
+        /// The slots that jumped to the removed instruction.
+        jumps: Vec<usize>,
+    },
+    Move {
+        from: usize,
+        to: usize,
+    },
+    Clear {
+        code: Vec<Instruction>,
+    },
+    /// An operand of the instruction at `slot` changed.
+    Change {
+        slot: usize,
+        old: Instruction,
+        new: Instruction,
+    },
+    /// The whole program was swapped for another one.
+    Replace {
+        old: Vec<Instruction>,
+        new: Vec<Instruction>,
+    },
+}
+
+impl ProgramEdit {
+    fn redo(&self, program_code: &mut ProgramCode) {
+        match self {
+            ProgramEdit::Insert { slot, instruction } => {
+                insert_instruction(program_code, *slot, *instruction);
+            }
+            ProgramEdit::Remove { slot, .. } => {
+                remove_instruction(program_code, *slot);
+            }
+            ProgramEdit::Move { from, to } => {
+                move_instruction(program_code, *from, *to);
+            }
+            ProgramEdit::Clear { .. } => program_code.code.clear(),
+            ProgramEdit::Change { slot, new, .. } => {
+                if let Some(instruction) = program_code.code.get_mut(*slot) {
+                    *instruction = *new;
+                }
+            }
+            ProgramEdit::Replace { new, .. } => program_code.code = new.clone(),
+        }
+    }
+
+    fn undo(&self, program_code: &mut ProgramCode) {
+        match self {
+            ProgramEdit::Insert { slot, .. } => {
+                remove_instruction(program_code, *slot);
+            }
+            ProgramEdit::Remove {
+                slot,
+                instruction,
+                jumps,
+            } => {
+                insert_instruction(program_code, *slot, *instruction);
+                // Removing sent these to the next instruction, and inserting
+                // shifted them past the restored one.
+                for jump in jumps {
+                    if let Some(jumping) = program_code.code.get_mut(*jump) {
+                        *jumping = jumping.with_target(*slot);
+                    }
+                }
+            }
+            ProgramEdit::Move { from, to } => {
+                move_instruction(program_code, *to, *from);
+            }
+            ProgramEdit::Clear { code } => program_code.code = code.clone(),
+            ProgramEdit::Change { slot, old, .. } => {
+                if let Some(instruction) = program_code.code.get_mut(*slot) {
+                    *instruction = *old;
+                }
+            }
+            ProgramEdit::Replace { old, .. } => program_code.code = old.clone(),
+        }
+    }
+}
+
+/// The edits that can be undone and redone, each with the [`RobotIndex`] of
+/// the robot whose program it changed.
+#[derive(Resource, Reflect, Debug, Clone, Default)]
+#[reflect(Resource)]
+pub struct EditHistory {
+    /// Oldest first, so the oldest can be dropped once it's full.
+    undo: VecDeque<(usize, ProgramEdit)>,
+    redo: Vec<(usize, ProgramEdit)>,
+}
+
+impl EditHistory {
+    fn record(&mut self, robot: usize, edit: ProgramEdit) {
+        self.undo.push_back((robot, edit));
+        if self.undo.len() > HISTORY_SIZE {
+            self.undo.pop_front();
+        }
+        self.redo.clear();
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+/// Edits the selected robot's program, recording each edit in the
+/// [`EditHistory`].
+#[derive(SystemParam)]
+pub struct ProgramEditor<'w, 's> {
+    selected: Res<'w, SelectedRobot>,
+    programs: Query<'w, 's, (&'static RobotIndex, &'static mut ProgramCode)>,
+    history: ResMut<'w, EditHistory>,
+}
+
+impl ProgramEditor<'_, '_> {
+    pub fn get(&self) -> Option<&ProgramCode> {
+        self.program(self.selected.0)
+    }
+
+    /// The program of the robot with [`RobotIndex`] `robot`.
+    pub fn program(&self, robot: usize) -> Option<&ProgramCode> {
+        self.programs
+            .iter()
+            .find(|(index, _)| index.0 == robot)
+            .map(|(_, program_code)| program_code)
+    }
+
+    fn program_mut(&mut self, robot: usize) -> Option<Mut<'_, ProgramCode>> {
+        self.programs
+            .iter_mut()
+            .find(|(index, _)| index.0 == robot)
+            .map(|(_, program_code)| program_code)
+    }
+
+    /// Inserts `instruction` at `slot`, or at the end if `slot` is past it.
+    /// Returns the slot it ended up in, or `None` if the program is full.
+    pub fn insert(&mut self, slot: usize, instruction: Instruction) -> Option<usize> {
+        let robot = self.selected.0;
+        let slot = insert_instruction(&mut *self.program_mut(robot)?, slot, instruction)?;
+        self.history
+            .record(robot, ProgramEdit::Insert { slot, instruction });
+        Some(slot)
+    }
+
+    pub fn remove(&mut self, slot: usize) -> Option<Instruction> {
+        let robot = self.selected.0;
+        let mut program_code = self.program_mut(robot)?;
+        let jumps = program_code
+            .code
+            .iter()
+            .enumerate()
+            .filter(|(jump, instruction)| *jump != slot && instruction.target() == Some(slot))
+            .map(|(jump, _)| jump)
+            .collect();
+        let instruction = remove_instruction(&mut program_code, slot)?;
+        self.history.record(
+            robot,
+            ProgramEdit::Remove {
+                slot,
+                instruction,
+                jumps,
+            },
+        );
+        Some(instruction)
+    }
+
+    /// Moves the instruction at `from` to `to`, or to the end if `to` is past
+    /// it. Returns the slot it ended up in.
+    pub fn move_to(&mut self, from: usize, to: usize) -> Option<usize> {
+        let robot = self.selected.0;
+        let to = move_instruction(&mut *self.program_mut(robot)?, from, to)?;
+        if to != from {
+            self.history.record(robot, ProgramEdit::Move { from, to });
+        }
+        Some(to)
+    }
+
+    pub fn clear(&mut self) {
+        let robot = self.selected.0;
+        let Some(mut program_code) = self.program_mut(robot) else {
+            return;
+        };
+        if program_code.code.is_empty() {
+            return;
+        }
+        let code = std::mem::take(&mut program_code.code);
+        self.history.record(robot, ProgramEdit::Clear { code });
+    }
+
+    /// Replaces the instruction at `slot` with `instruction`, which is meant
+    /// to be the same instruction with other operands.
+    pub fn change(&mut self, slot: usize, instruction: Instruction) {
+        let robot = self.selected.0;
+        let Some(mut program_code) = self.program_mut(robot) else {
+            return;
+        };
+        let Some(old) = program_code.code.get_mut(slot) else {
+            return;
+        };
+        let edit = ProgramEdit::Change {
+            slot,
+            old: *old,
+            new: instruction,
+        };
+        *old = instruction;
+        self.history.record(robot, edit);
+    }
+
+    /// Swaps the whole program of the robot with [`RobotIndex`] `robot` for
+    /// `code`.
+    pub fn replace(&mut self, robot: usize, code: Vec<Instruction>) {
+        let Some(mut program_code) = self.program_mut(robot) else {
+            return;
+        };
+        let old = std::mem::replace(&mut program_code.code, code.clone());
+        self.history
+            .record(robot, ProgramEdit::Replace { old, new: code });
+    }
+
+    pub fn undo(&mut self) {
+        let Some((robot, edit)) = self.history.undo.pop_back() else {
+            return;
+        };
+        let Some(mut program_code) = self.program_mut(robot) else {
+            tracing::warn!("Robot {robot} is gone, dropping {:?}", edit);
+            return;
+        };
+        tracing::info!("Undoing {:?}", edit);
+        edit.undo(&mut program_code);
+        self.history.redo.push((robot, edit));
+    }
+
+    pub fn redo(&mut self) {
+        let Some((robot, edit)) = self.history.redo.pop() else {
+            return;
+        };
+        let Some(mut program_code) = self.program_mut(robot) else {
+            tracing::warn!("Robot {robot} is gone, dropping {:?}", edit);
+            return;
+        };
+        tracing::info!("Redoing {:?}", edit);
+        edit.redo(&mut program_code);
+        self.history.undo.push_back((robot, edit));
+    }
+}
+
+pub fn undo_edit<E: Event>(_: Trigger<E>, mut editor: ProgramEditor) {
+    editor.undo();
+}
+
+pub fn redo_edit<E: Event>(_: Trigger<E>, mut editor: ProgramEditor) {
+    editor.redo();
+}
+
+pub fn clear_program(_: Trigger<Pointer<Click>>, mut editor: ProgramEditor) {
+    tracing::info!("Clearing program");
+    editor.clear();
+}
+
+fn clear_history(mut history: ResMut<EditHistory>) {
+    history.clear();
+}
+
+/// The next level has other robots, so edits to this one's can't be undone.
+fn forget_level_history(_: Trigger<LevelCompleted>, mut history: ResMut<EditHistory>) {
+    history.clear();
+}
+
+/// Drops a dragged program instruction or palette entry onto the
+/// [`DropSlot`] the observer is attached to.
+pub fn drop_on_slot(
+    trigger: Trigger<Pointer<DragDrop>>,
+    slots: Query<&DropSlot>,
+    parents: Query<&ChildOf>,
+    items: Query<&InstructionItem>,
+    palette: Query<&PaletteInstruction>,
+    mut editor: ProgramEditor,
+) {
+    let Ok(DropSlot(slot)) = slots.get(trigger.target()) else {
+        return;
+    };
+    // Drags start on whatever was pressed, which may be a button in the entry.
+    let dropped = trigger.event().dropped;
+    for entity in std::iter::once(dropped).chain(parents.iter_ancestors(dropped)) {
+        if let Ok(InstructionItem(from)) = items.get(entity) {
+            if from != slot {
+                tracing::info!("Moving instruction from slot {from} to slot {slot}");
+                editor.move_to(*from, *slot);
+            }
+            return;
+        }
+        if let Ok(PaletteInstruction(instruction)) = palette.get(entity) {
+            tracing::info!("Inserting {:?} at slot {slot}", instruction);
+            editor.insert(*slot, *instruction);
+            return;
+        }
+    }
+}
+
+/// Outlines the slot a dragged instruction would land in.
+pub fn highlight_drop_slot(trigger: Trigger<Pointer<DragEnter>>, mut commands: Commands) {
+    commands.entity(trigger.target()).insert(Outline {
+        width: Val::Px(2.0),
+        offset: Val::Px(0.0),
+        color: MEDIUM_SPRING_GREEN.into(),
+    });
+}
+
+/// Drops the outline once the drag moves on.
+pub fn clear_drop_slot(trigger: Trigger<Pointer<DragLeave>>, mut commands: Commands) {
+    commands.entity(trigger.target()).remove::<Outline>();
+}
+
+/// The slot keyboard edits happen at. Inserting puts the new instruction
+/// here, pushing the one already here down.
+#[derive(Resource, Reflect, Debug, Clone, Copy, Default)]
+#[reflect(Resource)]
+pub struct EditorCursor {
+    pub slot: usize,
+}
+
+impl EditorCursor {
+    /// The cursor's slot, kept within the slots of `program_code`.
+    fn clamped(&self, program_code: &ProgramCode) -> usize {
+        self.slot
+            .min(program_code.max_instructions.saturating_sub(1))
+    }
+
+    fn set(&mut self, slot: usize, program_code: &ProgramCode) {
+        self.slot = slot.min(program_code.max_instructions.saturating_sub(1));
+    }
+}
+
+#[derive(InputContext, Default, Debug, Reflect)]
+#[input_context(priority = 0)]
+pub struct ProgramEditorContext;
+
+#[derive(InputAction, Reflect, Default, Debug)]
+#[input_action(output = bool, require_reset = true)]
+struct CursorUp;
+
+#[derive(InputAction, Reflect, Default, Debug)]
+#[input_action(output = bool, require_reset = true)]
+struct CursorDown;
+
+/// Inserts the palette entry of whichever number key was pressed.
+#[derive(InputAction, Reflect, Default, Debug)]
+#[input_action(output = bool, require_reset = true)]
+struct InsertFromPalette;
+
+#[derive(InputAction, Reflect, Default, Debug)]
+#[input_action(output = bool, require_reset = true)]
+struct DeleteInstruction;
+
+#[derive(InputAction, Reflect, Default, Debug)]
+#[input_action(output = bool, require_reset = true)]
+struct MoveInstructionUp;
+
+#[derive(InputAction, Reflect, Default, Debug)]
+#[input_action(output = bool, require_reset = true)]
+struct MoveInstructionDown;
+
+#[derive(InputAction, Reflect, Default, Debug)]
+#[input_action(output = bool, require_reset = true)]
+struct UndoEdit;
+
+#[derive(InputAction, Reflect, Default, Debug)]
+#[input_action(output = bool, require_reset = true)]
+struct RedoEdit;
+
+fn spawn_editor_context(mut commands: Commands) {
+    commands.spawn((
+        Name::new("Program Editor Context"),
+        StateScoped(GameState::Playing),
+        Actions::<ProgramEditorContext>::default(),
+    ));
+}
+
+fn editor_binding(
+    trigger: Trigger<Binding<ProgramEditorContext>>,
+    mut actions: Query<&mut Actions<ProgramEditorContext>>,
+) {
+    let mut actions = actions.get_mut(trigger.target()).unwrap();
+    // Bindings with more modifiers are checked first and take the key press,
+    // so Ctrl+Shift+Z doesn't also undo and Ctrl+Up doesn't move the cursor.
+    actions
+        .bind::<RedoEdit>()
+        .to(KeyCode::KeyZ.with_mod_keys(ModKeys::CONTROL | ModKeys::SHIFT))
+        .with_conditions(Press::new(0.2));
+    actions
+        .bind::<UndoEdit>()
+        .to(KeyCode::KeyZ.with_mod_keys(ModKeys::CONTROL))
+        .with_conditions(Press::new(0.2));
+    actions
+        .bind::<MoveInstructionUp>()
+        .to(KeyCode::ArrowUp.with_mod_keys(ModKeys::CONTROL))
+        .with_conditions(Press::new(0.2));
+    actions
+        .bind::<MoveInstructionDown>()
+        .to(KeyCode::ArrowDown.with_mod_keys(ModKeys::CONTROL))
+        .with_conditions(Press::new(0.2));
+    actions
+        .bind::<CursorUp>()
+        .to(KeyCode::ArrowUp)
+        .with_conditions(Press::new(0.2));
+    actions
+        .bind::<CursorDown>()
+        .to(KeyCode::ArrowDown)
+        .with_conditions(Press::new(0.2));
+    actions
+        .bind::<InsertFromPalette>()
+        .to(PALETTE_KEYS)
+        .with_conditions(Press::new(0.2));
+    actions
+        .bind::<DeleteInstruction>()
+        .to((KeyCode::Delete, KeyCode::Backspace))
+        .with_conditions(Press::new(0.2));
+}
+
+fn cursor_up(
+    _: Trigger<Fired<CursorUp>>,
+    program: SelectedProgram,
+    mut cursor: ResMut<EditorCursor>,
+) {
+    let Some(program_code) = program.get() else {
+        return;
+    };
+    cursor.slot = cursor.clamped(program_code).saturating_sub(1);
+}
+
+fn cursor_down(
+    _: Trigger<Fired<CursorDown>>,
+    program: SelectedProgram,
+    mut cursor: ResMut<EditorCursor>,
+) {
+    let Some(program_code) = program.get() else {
+        return;
+    };
+    let slot = cursor.clamped(program_code) + 1;
+    cursor.set(slot, program_code);
+}
+
+fn insert_from_palette(
+    _: Trigger<Fired<InsertFromPalette>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    unlocked_instructions: Option<Res<UnlockedInstructions>>,
+    conditional_builder: Res<ConditionalBuilder>,
+    definitions: Res<InstructionDefinitions>,
+    mut editor: ProgramEditor,
+    mut cursor: ResMut<EditorCursor>,
+) {
+    let Some(unlocked_instructions) = unlocked_instructions else {
+        return;
+    };
+    let Some(key) = PALETTE_KEYS.iter().position(|key| keys.just_pressed(*key)) else {
+        return;
+    };
+    let palette = InstructionType::ALL.into_iter().flat_map(|inst_type| {
+        unlocked_instructions.in_palette(inst_type, &conditional_builder, &definitions)
+    });
+    let Some(instruction) = palette.into_iter().nth(key) else {
+        return;
+    };
+    let Some(slot) = editor
+        .get()
+        .map(|program_code| cursor.clamped(program_code))
+    else {
+        return;
+    };
+    tracing::info!("Inserting {:?} at slot {slot}", instruction);
+    let Some(slot) = editor.insert(slot, instruction) else {
+        return;
+    };
+    if let Some(program_code) = editor.get() {
+        cursor.set(slot + 1, program_code);
+    }
+}
+
+fn delete_at_cursor(
+    _: Trigger<Fired<DeleteInstruction>>,
+    mut editor: ProgramEditor,
+    cursor: Res<EditorCursor>,
+) {
+    let Some(slot) = editor
+        .get()
+        .map(|program_code| cursor.clamped(program_code))
+    else {
+        return;
+    };
+    if let Some(instruction) = editor.remove(slot) {
+        tracing::info!("Removed {:?} from slot {slot}", instruction);
+    }
+}
+
+fn move_up(
+    _: Trigger<Fired<MoveInstructionUp>>,
+    mut editor: ProgramEditor,
+    mut cursor: ResMut<EditorCursor>,
+) {
+    let Some(slot) = editor
+        .get()
+        .map(|program_code| cursor.clamped(program_code))
+    else {
+        return;
+    };
+    if slot == 0 {
+        return;
+    }
+    if let Some(slot) = editor.move_to(slot, slot - 1) {
+        cursor.slot = slot;
+    }
+}
+
+fn move_down(
+    _: Trigger<Fired<MoveInstructionDown>>,
+    mut editor: ProgramEditor,
+    mut cursor: ResMut<EditorCursor>,
+) {
+    let Some(slot) = editor
+        .get()
+        .map(|program_code| cursor.clamped(program_code))
+    else {
+        return;
+    };
+    if let Some(slot) = editor.move_to(slot, slot + 1) {
+        cursor.slot = slot;
+    }
+}
+
+/// Marks the slot number of the cursor's slot.
+fn highlight_cursor(
+    mut commands: Commands,
+    cursor: Res<EditorCursor>,
+    program: SelectedProgram,
+    labels: Query<(Entity, &SlotLabel)>,
+) {
+    let Some(program_code) = program.get() else {
+        return;
+    };
+    let slot = cursor.clamped(program_code);
+    for (entity, SlotLabel(label)) in &labels {
+        let color = if *label == slot {
+            DARK_OLIVEGREEN.into()
+        } else {
+            Color::NONE
+        };
+        commands.entity(entity).insert(BackgroundColor(color));
+    }
+}
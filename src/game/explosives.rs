@@ -0,0 +1,168 @@
+//! Crates that robots blow up with [`Instruction::Detonate`](super::cpu::Instruction::Detonate).
+//!
+//! The [`Simulation`](super::simulation::Simulation) decides what explodes and
+//! when. Explosions spread to neighboring crates one tick at a time, clear the
+//! walls around them and uncover any gem a crate was sitting on. The
+//! observers here only mirror that onto the spawned level.
+
+use std::time::Duration;
+
+use bevy::{color::palettes::css::*, prelude::*};
+use bevy_ecs_ldtk::{GridCoords, LdtkEntity, utils::grid_coords_to_translation};
+
+use crate::state::GameState;
+
+use super::{
+    level::{FLOOR_TILE, Floor, LevelAssets},
+    objects::GemObject,
+};
+
+/// How long the flash of an explosion stays on screen.
+const EXPLOSION_DURATION: Duration = Duration::from_millis(400);
+
+pub(super) fn plugin(app: &mut App) {
+    app.register_type::<CrateObject>()
+        .register_type::<CrateExploded>()
+        .register_type::<WallCleared>()
+        .register_type::<Explosion>()
+        .register_type::<ClearedWall>();
+    app.add_systems(
+        Update,
+        (
+            hide_covered_gems.run_if(any_match_filter::<Added<CrateObject>>),
+            fade_explosions,
+        )
+            .run_if(in_state(GameState::Playing)),
+    );
+    app.add_observer(crate_exploded).add_observer(wall_cleared);
+}
+
+#[derive(Component, Default, Reflect, Debug, Clone)]
+pub struct CrateObject;
+
+#[derive(Bundle, LdtkEntity, Default)]
+pub struct CrateBundle {
+    crate_object: CrateObject,
+
+    #[sprite("images/sprites/crate.png")]
+    sprite: Sprite,
+    #[grid_coords]
+    grid_coords: GridCoords,
+}
+
+/// Triggered when a crate explodes.
+#[derive(Event, Reflect, Debug, Clone, Copy)]
+pub struct CrateExploded {
+    pub coords: GridCoords,
+}
+
+/// Triggered when an explosion turns a wall into floor.
+#[derive(Event, Reflect, Debug, Clone, Copy)]
+pub struct WallCleared {
+    pub coords: GridCoords,
+}
+
+/// The flash left behind by an exploding crate.
+#[derive(Component, Reflect, Debug, Clone)]
+pub struct Explosion {
+    pub timer: Timer,
+}
+
+/// A floor tile drawn over a wall that was blown away.
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+pub struct ClearedWall;
+
+/// The floor tile shown where a wall at `coords` used to be.
+pub fn cleared_wall(coords: GridCoords, level_assets: &LevelAssets) -> impl Bundle {
+    let tile = FLOOR_TILE.as_vec2() * 18.0;
+    (
+        Name::new("Cleared Wall"),
+        ClearedWall,
+        coords,
+        Sprite {
+            image: level_assets.tiles.clone(),
+            rect: Some(Rect::from_corners(tile, tile + Vec2::splat(18.0))),
+            ..default()
+        },
+        Transform::from_translation(
+            grid_coords_to_translation(coords, IVec2::splat(18)).extend(0.0),
+        ),
+    )
+}
+
+/// Gems under a crate stay hidden until it explodes.
+fn hide_covered_gems(
+    crates: Query<&GridCoords, With<CrateObject>>,
+    mut gems: Query<(&GridCoords, &mut Visibility), With<GemObject>>,
+) {
+    for (coords, mut visibility) in &mut gems {
+        if crates.iter().any(|crate_coords| crate_coords == coords) {
+            *visibility = Visibility::Hidden;
+        }
+    }
+}
+
+fn crate_exploded(
+    trigger: Trigger<CrateExploded>,
+    mut commands: Commands,
+    crates: Query<(Entity, &GridCoords, &ChildOf), With<CrateObject>>,
+    mut gems: Query<(&GridCoords, &mut Visibility), With<GemObject>>,
+) {
+    let coords = trigger.event().coords;
+    tracing::info!("Crate exploded at {:?}", coords);
+    for (crate_entity, crate_coords, child_of) in &crates {
+        if *crate_coords != coords {
+            continue;
+        }
+        commands.entity(crate_entity).despawn();
+        commands.spawn((
+            Name::new("Explosion"),
+            Explosion {
+                timer: Timer::new(EXPLOSION_DURATION, TimerMode::Once),
+            },
+            Sprite::from_color(ORANGE_RED, Vec2::splat(18.0)),
+            Transform::from_translation(
+                grid_coords_to_translation(coords, IVec2::splat(18)).extend(10.0),
+            ),
+            ChildOf(child_of.parent()),
+        ));
+    }
+    for (gem_coords, mut visibility) in &mut gems {
+        if *gem_coords == coords {
+            *visibility = Visibility::Inherited;
+        }
+    }
+}
+
+fn wall_cleared(
+    trigger: Trigger<WallCleared>,
+    mut commands: Commands,
+    level_assets: Res<LevelAssets>,
+    floors: Query<&ChildOf, With<Floor>>,
+) {
+    let coords = trigger.event().coords;
+    tracing::info!("Explosion cleared the wall at {:?}", coords);
+    let Some(floor_layer) = floors.iter().next() else {
+        return;
+    };
+    commands.spawn((
+        cleared_wall(coords, &level_assets),
+        ChildOf(floor_layer.parent()),
+    ));
+}
+
+fn fade_explosions(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut explosions: Query<(Entity, &mut Explosion, &mut Sprite)>,
+) {
+    for (entity, mut explosion, mut sprite) in &mut explosions {
+        if explosion.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        sprite.color = sprite
+            .color
+            .with_alpha(explosion.timer.fraction_remaining());
+    }
+}
@@ -0,0 +1,149 @@
+//! How every instruction is presented and priced, loaded from
+//! `data/instructions.ron` so designers can tune it without recompiling.
+//!
+//! Definitions are keyed by the instruction's assembly mnemonic. Names,
+//! descriptions and categories update as soon as the file is saved; cycle
+//! costs are read when a level starts.
+
+use std::collections::HashMap;
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_common_assets::ron::RonAssetPlugin;
+use serde::Deserialize;
+
+use crate::state::GameState;
+
+use super::{
+    cpu::{CycleCosts, Instruction, InstructionAssets, InstructionType},
+    level::spawn_level_ui,
+};
+
+/// The width and height of an icon tile, matching the rows of the program grid.
+const ICON_SIZE: f32 = 20.0;
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_plugins(RonAssetPlugin::<InstructionDefinitions>::new(&[
+        "instructions.ron",
+    ]));
+    app.register_type::<InstructionDefinitions>()
+        .register_type::<InstructionDefinition>();
+    app.add_systems(
+        OnEnter(GameState::Playing),
+        setup_instruction_definitions.before(spawn_level_ui),
+    );
+    app.add_systems(
+        Update,
+        reload_instruction_definitions
+            .run_if(on_event::<AssetEvent<InstructionDefinitions>>)
+            .run_if(in_state(GameState::Playing)),
+    );
+}
+
+#[derive(Reflect, Deserialize, Debug, Clone)]
+pub struct InstructionDefinition {
+    pub name: String,
+    pub description: String,
+    pub category: InstructionType,
+    /// The icon image, relative to the assets folder.
+    pub icon: String,
+    /// How many [`Tick`](super::ticks::Tick)s the instruction keeps the CPU
+    /// busy for.
+    pub cycles: u32,
+}
+
+#[derive(Resource, Asset, Reflect, Deserialize, Debug, Clone, Default)]
+#[reflect(Resource)]
+pub struct InstructionDefinitions {
+    /// Every definition, by the mnemonic of its instruction.
+    pub instructions: HashMap<String, InstructionDefinition>,
+}
+
+impl InstructionDefinitions {
+    pub fn get(&self, instruction: &Instruction) -> Option<&InstructionDefinition> {
+        self.instructions.get(instruction.mnemonic())
+    }
+
+    /// The display name of `instruction`, or its mnemonic if it isn't defined.
+    pub fn name(&self, instruction: &Instruction) -> &str {
+        self.get(instruction)
+            .map_or(instruction.mnemonic(), |definition| &definition.name)
+    }
+
+    /// The palette category of `instruction`. Anything undefined is control flow.
+    pub fn category(&self, instruction: &Instruction) -> InstructionType {
+        self.get(instruction)
+            .map_or(InstructionType::Control, |definition| definition.category)
+    }
+
+    pub fn cycle_costs(&self) -> CycleCosts {
+        CycleCosts(
+            self.instructions
+                .iter()
+                .map(|(mnemonic, definition)| (mnemonic.clone(), definition.cycles))
+                .collect(),
+        )
+    }
+}
+
+/// Icon tiles for instructions, tinted by category.
+#[derive(SystemParam)]
+pub struct InstructionIcons<'w> {
+    asset_server: Res<'w, AssetServer>,
+    instruction_assets: Res<'w, InstructionAssets>,
+    definitions: Res<'w, InstructionDefinitions>,
+}
+
+impl InstructionIcons<'_> {
+    pub fn tile(&self, instruction: &Instruction) -> impl Bundle {
+        let category = self.definitions.category(instruction);
+        let image = self.definitions.get(instruction).map_or_else(
+            || self.instruction_assets.icon(category),
+            |definition| self.asset_server.load(&definition.icon),
+        );
+        (
+            Name::new("Instruction Icon"),
+            ImageNode::new(image).with_color(category.color()),
+            Node {
+                width: Val::Px(ICON_SIZE),
+                height: Val::Px(ICON_SIZE),
+                flex_shrink: 0.0,
+                margin: UiRect::right(Val::Px(5.0)),
+                ..default()
+            },
+            Pickable::IGNORE,
+        )
+    }
+}
+
+fn setup_instruction_definitions(
+    mut commands: Commands,
+    instruction_assets: Res<InstructionAssets>,
+    definitions: Res<Assets<InstructionDefinitions>>,
+) {
+    let Some(definitions) = definitions.get(&instruction_assets.definitions) else {
+        tracing::warn!("Instruction definitions aren't loaded");
+        commands.init_resource::<InstructionDefinitions>();
+        return;
+    };
+    commands.insert_resource(definitions.clone());
+}
+
+fn reload_instruction_definitions(
+    mut events: EventReader<AssetEvent<InstructionDefinitions>>,
+    instruction_assets: Res<InstructionAssets>,
+    definitions: Res<Assets<InstructionDefinitions>>,
+    mut current: ResMut<InstructionDefinitions>,
+) {
+    for event in events.read() {
+        if !event.is_modified(&instruction_assets.definitions) {
+            continue;
+        }
+        if let Some(definitions) = definitions.get(&instruction_assets.definitions) {
+            tracing::info!(
+                "Reloaded {} instruction definitions",
+                definitions.instructions.len()
+            );
+            *current = definitions.clone();
+        }
+    }
+}
@@ -1,12 +1,15 @@
 //! Spawn the main level.
 
-use std::{fmt::Debug, time::Duration};
+use std::{collections::HashSet, fmt::Debug, time::Duration};
 
 use bevy::{
     asset::RenderAssetUsages,
     color::palettes::css::*,
     prelude::*,
-    render::render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages},
+    render::{
+        render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages},
+        view::RenderLayers,
+    },
 };
 use bevy_asset_loader::{
     asset_collection::AssetCollection,
@@ -15,7 +18,11 @@ use bevy_asset_loader::{
         config::{ConfigureLoadingState, LoadingStateConfig},
     },
 };
-use bevy_ecs_ldtk::{LdtkWorldBundle, LevelSelection, app::LdtkEntityAppExt, assets::LdtkProject};
+use bevy_ecs_ldtk::{
+    GridCoords, LdtkIntCell, LdtkWorldBundle, LevelEvent, LevelSelection,
+    app::{LdtkEntityAppExt, LdtkIntCellAppExt},
+    assets::LdtkProject,
+};
 use bevy_ecs_tilemap::map::{TilemapGridSize, TilemapSize, TilemapTileSize};
 
 use bevy_enhanced_input::{
@@ -25,13 +32,21 @@ use bevy_enhanced_input::{
 #[cfg(feature = "dev_native")]
 use bevy_simple_subsecond_system::hot;
 
+#[cfg(not(target_family = "wasm"))]
+use crate::game::program_files::{load_program, save_program};
 use crate::{
     Pause, UiCamera,
     audio::music,
     game::{
-        cpu::{CpuOptions, CpuSpeedDisplay},
+        cpu::{CpuOptions, CpuSpeedDisplay, LoopsDisplay, REGISTER_COUNT, RegistersDisplay},
+        debugger::{DebugButton, continue_program, pause_program, step_program},
+        editor::{clear_program, redo_edit, undo_edit},
+        explosives::CrateBundle,
+        instructions::InstructionDefinitions,
         objects::{GemBundle, GemDisplay, TimeToBomb, TimeToBombDisplay},
         player::PlayerBundle,
+        rewind::{RewindButton, RewindDisplay, rewind_backward, rewind_forward},
+        robots::{SelectedRobotDisplay, select_next_robot, select_previous_robot},
         ticks::{reset_simulation, start_simulation},
     },
     menu::Menu,
@@ -42,15 +57,34 @@ use crate::{
 #[allow(dead_code)]
 pub const LEVEL_SCALE_FACTOR: f32 = 4.0;
 
+/// The `Blank_Floor` value of the `Floors` IntGrid layer in `mazes.ldtk`.
+pub const BLANK_FLOOR: i32 = 1;
+
+/// The column and row of the tile `Blank_Floor` cells are drawn with in
+/// `marble_packed.png`.
+pub const FLOOR_TILE: UVec2 = UVec2::new(7, 4);
+
 pub(super) fn plugin(app: &mut App) {
-    app.register_type::<LevelAssets>();
+    app.register_type::<LevelAssets>()
+        .register_type::<LevelGrid>()
+        .register_type::<Floor>();
+    app.init_resource::<LevelGrid>();
     app.configure_loading_state(
         LoadingStateConfig::new(GameState::Loading).load_collection::<LevelAssets>(),
     );
-    app.add_systems(OnEnter(GameState::Playing), spawn_level_ui);
+    app.add_systems(
+        OnEnter(GameState::Playing),
+        (start_at_first_level, spawn_level_ui),
+    );
     app.add_systems(OnEnter(ProgramState::Buying), spawn_level);
     app.register_ldtk_entity::<PlayerBundle>("Player");
     app.register_ldtk_entity::<GemBundle>("Blue_gear");
+    app.register_ldtk_entity::<CrateBundle>("Crate");
+    app.register_ldtk_int_cell_for_layer::<FloorBundle>("Floors", BLANK_FLOOR);
+    app.add_systems(
+        Update,
+        cache_floor_locations.run_if(in_state(GameState::Playing)),
+    );
     app.add_input_context::<LevelContext>();
     app.add_observer(level_context_binding)
         .add_observer(pause_game);
@@ -78,6 +112,55 @@ pub struct TilemapMetadata {
     pub scale_factor: f32,
 }
 
+#[derive(Component, Reflect, Debug, Clone, Copy, Default)]
+#[reflect(Component)]
+pub struct Floor;
+
+#[derive(Bundle, LdtkIntCell, Default)]
+pub struct FloorBundle {
+    floor: Floor,
+}
+
+/// The walkable cells of the currently spawned level.
+#[derive(Resource, Reflect, Debug, Clone, Default)]
+#[reflect(Resource)]
+pub struct LevelGrid {
+    pub floors: HashSet<GridCoords>,
+}
+
+impl LevelGrid {
+    pub fn is_floor(&self, coords: GridCoords) -> bool {
+        self.floors.contains(&coords)
+    }
+
+    /// Whether `coords` lies within the bounding box of the floor, so the
+    /// walls around the level can never be blown open.
+    pub fn in_bounds(&self, coords: GridCoords) -> bool {
+        let xs = self.floors.iter().map(|floor| floor.x);
+        let ys = self.floors.iter().map(|floor| floor.y);
+        xs.clone().min().is_some_and(|min| min <= coords.x)
+            && xs.max().is_some_and(|max| coords.x <= max)
+            && ys.clone().min().is_some_and(|min| min <= coords.y)
+            && ys.max().is_some_and(|max| coords.y <= max)
+    }
+}
+
+fn cache_floor_locations(
+    mut level_events: EventReader<LevelEvent>,
+    floors: Query<&GridCoords, With<Floor>>,
+    mut level_grid: ResMut<LevelGrid>,
+) {
+    for level_event in level_events.read() {
+        if let LevelEvent::Spawned(level_iid) = level_event {
+            level_grid.floors = floors.iter().copied().collect();
+            tracing::info!(
+                "Cached {} floor cells for level {level_iid:?}",
+                level_grid.floors.len()
+            );
+        }
+    }
+}
+
 #[derive(Component, Reflect, Debug, Clone, Copy)]
 pub struct ResetButton;
 
@@ -89,8 +172,10 @@ fn control_panel() -> impl Bundle {
         Name::new("Control Panel"),
         Node {
             flex_direction: FlexDirection::Row,
+            flex_wrap: FlexWrap::Wrap,
             align_items: AlignItems::Center,
             justify_content: JustifyContent::SpaceBetween,
+            row_gap: Val::Px(5.0),
             width: Val::Vw(33.3),
             margin: UiRect::left(Val::Px(5.0)),
             border: UiRect::left(Val::Px(10.0)),
@@ -102,6 +187,29 @@ fn control_panel() -> impl Bundle {
             // Controls
             widget::button_medium("Reset", reset_simulation, (Inactive, ResetButton)),
             widget::button_medium("Run", start_simulation, RunButton),
+            // Debugger
+            widget::button_medium("Pause", pause_program, (Inactive, DebugButton)),
+            widget::button_medium("Step", step_program, (Inactive, DebugButton)),
+            widget::button_medium("Continue", continue_program, (Inactive, DebugButton)),
+            rewind_scrubber(),
+        ],
+    )
+}
+
+fn rewind_scrubber() -> impl Bundle {
+    (
+        Name::new("Rewind Scrubber"),
+        Node {
+            flex_direction: FlexDirection::Row,
+            align_items: AlignItems::Center,
+            column_gap: Val::Px(5.0),
+            ..default()
+        },
+        Pickable::IGNORE,
+        children![
+            widget::button_medium("<", rewind_backward, (Inactive, RewindButton)),
+            (widget::label("Tick 0/0"), RewindDisplay),
+            widget::button_medium(">", rewind_forward, (Inactive, RewindButton)),
         ],
     )
 }
@@ -109,6 +217,10 @@ fn control_panel() -> impl Bundle {
 #[derive(Component, Reflect, Debug, Clone, Copy)]
 pub struct LevelCamera;
 
+/// The render layer for overlays that only the [`LevelCamera`] shows, on top
+/// of the default layer the level itself is on.
+pub const LEVEL_OVERLAY_LAYER: usize = 1;
+
 pub fn level_camera(commands: &mut Commands, images: &mut ResMut<Assets<Image>>) -> Handle<Image> {
     let size = Extent3d {
         width: 1024,
@@ -131,6 +243,7 @@ pub fn level_camera(commands: &mut Commands, images: &mut ResMut<Assets<Image>>)
         Camera2d,
         UiRoot,
         LevelCamera,
+        RenderLayers::from_layers(&[0, LEVEL_OVERLAY_LAYER]),
         StateScoped(GameState::Playing),
         Camera {
             target: image_handle.clone().into(),
@@ -180,6 +293,7 @@ pub fn spawn_level_ui(
     camera: Single<Entity, With<UiCamera>>,
     old_ui: Query<Entity, With<UiRoot>>,
     mut images: ResMut<Assets<Image>>,
+    instruction_definitions: Res<InstructionDefinitions>,
 ) {
     for root in &old_ui {
         commands.entity(root).despawn();
@@ -234,6 +348,9 @@ pub fn spawn_level_ui(
     commands.insert_resource(CpuOptions {
         cpu_tick: Duration::from_millis(100),
         multiplier: 1.0,
+        max_call_depth: 2,
+        cycle_costs: instruction_definitions.cycle_costs(),
+        loop_program: false,
     });
 }
 
@@ -379,6 +496,8 @@ fn stats_panel() -> impl Bundle {
                 ORANGE_RED.into()
             ),
             stat_display::<CpuSpeedDisplay>("CPU Inst", Duration::default(), DARK_GREEN.into(),),
+            stat_display::<RegistersDisplay>("Registers", [0; REGISTER_COUNT], DARK_GREEN.into()),
+            stat_display::<LoopsDisplay>("Loops", 0, DARK_GREEN.into()),
         ],
     )
 }
@@ -413,6 +532,9 @@ fn program_panel() -> impl Bundle {
         children![
             // Program
             widget::label("Program"),
+            robot_selector(),
+            program_file_controls(),
+            program_edit_controls(),
             (
                 Name::new("Program List"),
                 Node {
@@ -437,6 +559,60 @@ fn program_panel() -> impl Bundle {
     )
 }
 
+fn robot_selector() -> impl Bundle {
+    (
+        Name::new("Robot Selector"),
+        Node {
+            flex_direction: FlexDirection::Row,
+            align_items: AlignItems::Center,
+            column_gap: Val::Px(5.0),
+            ..default()
+        },
+        Pickable::IGNORE,
+        children![
+            widget::button_small("<", select_previous_robot),
+            (widget::label("Robot 1/1"), SelectedRobotDisplay),
+            widget::button_small(">", select_next_robot),
+        ],
+    )
+}
+
+fn program_file_controls() -> impl Bundle {
+    (
+        Name::new("Program File Controls"),
+        Node {
+            flex_direction: FlexDirection::Row,
+            column_gap: Val::Px(5.0),
+            padding: UiRect::vertical(Val::Px(5.0)),
+            ..default()
+        },
+        Pickable::IGNORE,
+        #[cfg(not(target_family = "wasm"))]
+        children![
+            widget::button_medium("Save", save_program, ()),
+            widget::button_medium("Load", load_program, ()),
+        ],
+    )
+}
+
+fn program_edit_controls() -> impl Bundle {
+    (
+        Name::new("Program Edit Controls"),
+        Node {
+            flex_direction: FlexDirection::Row,
+            column_gap: Val::Px(5.0),
+            padding: UiRect::vertical(Val::Px(5.0)),
+            ..default()
+        },
+        Pickable::IGNORE,
+        children![
+            widget::button_medium("Undo", undo_edit::<Pointer<Click>>, ()),
+            widget::button_medium("Redo", redo_edit::<Pointer<Click>>, ()),
+            widget::button_medium("Clear", clear_program, ()),
+        ],
+    )
+}
+
 fn stat_display<Comp: Component + Default>(
     label: impl Into<String>,
     default_value: impl Debug,
@@ -487,6 +663,11 @@ pub fn spawn_level(
         children![(Name::new("Gameplay Music"),),],
         Actions::<LevelContext>::default(),
     ));
+}
+
+/// A new game starts at the first level; [`spawn_level`] reloads whichever
+/// level is selected after that.
+fn start_at_first_level(mut commands: Commands) {
     commands.insert_resource(LevelSelection::index(0));
 }
 
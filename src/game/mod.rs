@@ -5,24 +5,50 @@
 
 use bevy::prelude::*;
 
+mod analysis;
 mod animation;
+pub mod assembly;
 pub mod cpu;
+pub mod debugger;
+pub mod editor;
+pub mod explosives;
+pub mod instructions;
 pub mod level;
 mod movement;
 pub mod objects;
 pub mod player;
+pub mod program_files;
+pub mod rewind;
+pub mod robots;
+pub mod scores;
+pub mod simulation;
+pub mod solver;
 pub mod ticks;
+pub mod trace;
 pub mod upgrades;
 
 pub(super) fn plugin(app: &mut App) {
     app.add_plugins((
         animation::plugin,
         cpu::plugin,
+        debugger::plugin,
+        editor::plugin,
+        explosives::plugin,
+        instructions::plugin,
         level::plugin,
         movement::plugin,
         objects::plugin,
         player::plugin,
+    ));
+    app.add_plugins((
+        program_files::plugin,
+        rewind::plugin,
+        robots::plugin,
+        scores::plugin,
+        simulation::plugin,
+        solver::plugin,
         ticks::plugin,
+        trace::plugin,
         upgrades::plugin,
     ));
 }
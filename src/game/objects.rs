@@ -3,28 +3,32 @@ use std::time::Duration;
 use bevy::prelude::*;
 use bevy_ecs_ldtk::{GridCoords, LdtkEntity, LevelSelection, assets::LevelIndices};
 
-#[cfg(feature = "dev_native")]
-use bevy_simple_subsecond_system::hot;
-
 use crate::state::ProgramState;
 
-use super::player::{PlayerObject, Wallet};
+use super::{debugger::program_paused, player::Wallet};
 
 pub(super) fn plugin(app: &mut App) {
-    app.register_type::<GemObject>();
+    app.register_type::<GemObject>()
+        .register_type::<GemCollected>()
+        .register_type::<AllGemsCollected>()
+        .register_type::<LevelCompleted>()
+        .register_type::<BombLow>();
     app.add_systems(
         FixedUpdate,
         (
             update_time_to_bomb_text.run_if(resource_exists_and_changed::<TimeToBomb>),
             update_gem_text.run_if(resource_exists_and_changed::<Wallet>),
-            pickup_gem.run_if(in_state(ProgramState::Running)),
         ),
     );
     app.add_systems(
         Update,
-        (tick_bomb_timer.run_if(in_state(ProgramState::Running)),),
+        (tick_bomb_timer
+            .run_if(in_state(ProgramState::Running))
+            .run_if(not(program_paused)),),
     );
-    app.add_observer(bomb_exploded);
+    app.add_observer(bomb_exploded)
+        .add_observer(collect_gem)
+        .add_observer(all_gems_collected);
 }
 
 #[derive(Component, Default, Reflect, Debug, Clone)]
@@ -60,11 +64,20 @@ pub struct TimeToBomb {
 #[derive(Resource, Reflect, Debug, Default, Clone)]
 pub struct BombTimer {
     pub timer: Timer,
+    /// Whether [`BombLow`] has fired for this timer.
+    pub low: bool,
 }
 
+/// How much time is left on the bomb when [`BombLow`] fires.
+pub const BOMB_LOW_THRESHOLD: Duration = Duration::from_secs(1);
+
 #[derive(Event, Reflect, Debug, Clone, Copy)]
 pub struct BombExploded;
 
+/// Triggered once per run, when the bomb drops below [`BOMB_LOW_THRESHOLD`].
+#[derive(Event, Reflect, Debug, Clone, Copy)]
+pub struct BombLow;
+
 fn update_time_to_bomb_text(
     mut text: Query<&mut Text, With<TimeToBombDisplay>>,
     time_to_bomb: Res<TimeToBomb>,
@@ -75,9 +88,19 @@ fn update_time_to_bomb_text(
 }
 
 fn tick_bomb_timer(mut commands: Commands, time: Res<Time>, mut bomb_timer: ResMut<BombTimer>) {
-    if bomb_timer.timer.tick(time.delta()).just_finished() {
+    advance_bomb_timer(&mut commands, &mut bomb_timer, time.delta());
+}
+
+/// Runs the bomb down by `delta`, triggering [`BombExploded`] and [`BombLow`]
+/// when they're due.
+pub fn advance_bomb_timer(commands: &mut Commands, bomb_timer: &mut BombTimer, delta: Duration) {
+    if bomb_timer.timer.tick(delta).just_finished() {
         commands.trigger(BombExploded);
     }
+    if !bomb_timer.low && bomb_timer.timer.remaining() < BOMB_LOW_THRESHOLD {
+        bomb_timer.low = true;
+        commands.trigger(BombLow);
+    }
 }
 
 fn bomb_exploded(_: Trigger<BombExploded>, mut next_state: ResMut<NextState<ProgramState>>) {
@@ -85,36 +108,55 @@ fn bomb_exploded(_: Trigger<BombExploded>, mut next_state: ResMut<NextState<Prog
     next_state.set(ProgramState::Buying);
 }
 
-#[cfg_attr(feature = "dev_native", hot)]
-fn pickup_gem(
+/// Triggered when a robot moves onto a gem.
+#[derive(Event, Reflect, Debug, Clone, Copy)]
+pub struct GemCollected {
+    pub coords: GridCoords,
+}
+
+/// Triggered when the last gem of a level is collected.
+#[derive(Event, Reflect, Debug, Clone, Copy)]
+pub struct AllGemsCollected;
+
+/// Triggered after [`AllGemsCollected`], just before switching to the next level.
+#[derive(Event, Reflect, Debug, Clone, Copy)]
+pub struct LevelCompleted {
+    /// The index of the level that was completed.
+    pub level: usize,
+}
+
+fn collect_gem(
+    trigger: Trigger<GemCollected>,
     mut commands: Commands,
-    players: Query<&GridCoords, With<PlayerObject>>,
     gems: Query<(Entity, &GridCoords), With<GemObject>>,
     mut wallet: ResMut<Wallet>,
-    mut next_state: ResMut<NextState<ProgramState>>,
-    mut level_selection: ResMut<LevelSelection>,
 ) {
-    let player_coords = players.single().unwrap();
+    let coords = trigger.event().coords;
     for (gem_entity, gem_coords) in &gems {
-        if *gem_coords == *player_coords {
+        if *gem_coords == coords {
             commands.entity(gem_entity).despawn();
             wallet.gems += 1;
             tracing::info!("Player picked up a gem at {:?}", gem_coords);
-            // Here you could also add logic to increase the player's score or inventory.
         }
     }
+}
 
-    if gems.iter().len() == 1 {
-        tracing::info!("All gems collected!");
-        next_state.set(ProgramState::Buying);
-        let LevelSelection::Indices(LevelIndices { level, .. }) = *level_selection else {
-            tracing::warn!(
-                "Expected LevelSelection::Indices, got {:?}",
-                level_selection
-            );
-            return;
-        };
-        tracing::info!("Switching to next level: {}", level + 1);
-        *level_selection = LevelSelection::index(level + 1);
-    }
+pub(super) fn all_gems_collected(
+    _: Trigger<AllGemsCollected>,
+    mut commands: Commands,
+    mut next_state: ResMut<NextState<ProgramState>>,
+    mut level_selection: ResMut<LevelSelection>,
+) {
+    tracing::info!("All gems collected!");
+    next_state.set(ProgramState::Buying);
+    let LevelSelection::Indices(LevelIndices { level, .. }) = *level_selection else {
+        tracing::warn!(
+            "Expected LevelSelection::Indices, got {:?}",
+            level_selection
+        );
+        return;
+    };
+    commands.trigger(LevelCompleted { level });
+    tracing::info!("Switching to next level: {}", level + 1);
+    *level_selection = LevelSelection::index(level + 1);
 }
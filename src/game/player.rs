@@ -23,7 +23,7 @@ pub struct Wallet {
 #[derive(Component, Debug, Clone, Copy, Reflect, Default)]
 pub struct PlayerObject;
 
-#[derive(Component, Debug, Clone, Copy, Reflect)]
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Reflect)]
 pub struct PlayerDirection(pub GridCoords);
 
 impl Default for PlayerDirection {
@@ -32,6 +32,24 @@ impl Default for PlayerDirection {
     }
 }
 
+impl PlayerDirection {
+    /// The direction 90 degrees counter-clockwise from this one.
+    pub fn left(&self) -> GridCoords {
+        GridCoords::new(-self.0.y, self.0.x)
+    }
+
+    /// The direction 90 degrees clockwise from this one.
+    pub fn right(&self) -> GridCoords {
+        GridCoords::new(self.0.y, -self.0.x)
+    }
+
+    /// The sprite rotation that faces this direction.
+    pub fn rotation(&self) -> Quat {
+        let angle = Vec2::X.angle_to(Vec2::new(self.0.x as f32, self.0.y as f32));
+        Quat::from_rotation_z(angle)
+    }
+}
+
 #[derive(Bundle, LdtkEntity, Default)]
 pub struct PlayerBundle {
     player: PlayerObject,
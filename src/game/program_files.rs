@@ -0,0 +1,202 @@
+//! Saving and loading [`ProgramCode`] as RON or JSON assets, so a good
+//! solution for a level can be kept and reused. Programs written by hand in
+//! the [assembly format](super::assembly) load as `.program.asm` files.
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, io::Reader},
+    prelude::*,
+};
+use bevy_common_assets::{json::JsonAssetPlugin, ron::RonAssetPlugin};
+#[cfg(not(target_family = "wasm"))]
+use bevy_ecs_ldtk::{LevelSelection, assets::LevelIndices};
+
+use crate::state::GameState;
+
+#[cfg(not(target_family = "wasm"))]
+use super::robots::{SelectedProgram, SelectedRobot};
+use super::{
+    assembly::parse_program,
+    cpu::{ProgramCode, UnlockedInstructions},
+    editor::ProgramEditor,
+};
+
+/// The asset directory programs are saved to.
+#[cfg(not(target_family = "wasm"))]
+pub const PROGRAMS_DIR: &str = "programs";
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_plugins((
+        RonAssetPlugin::<ProgramCode>::new(&["program.ron"]),
+        JsonAssetPlugin::<ProgramCode>::new(&["program.json"]),
+    ))
+    .init_asset_loader::<AssemblyLoader>();
+    app.add_systems(
+        Update,
+        apply_loaded_program
+            .run_if(resource_exists::<PendingProgram>)
+            .run_if(in_state(GameState::Playing)),
+    );
+}
+
+/// Reads a program from its assembly source. The program is as long as the
+/// source, and gets truncated to the robot's slots once it's applied.
+#[derive(Default)]
+struct AssemblyLoader;
+
+impl AssetLoader for AssemblyLoader {
+    type Asset = ProgramCode;
+    type Settings = ();
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<ProgramCode, Self::Error> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes).await?;
+        let code = parse_program(&String::from_utf8(bytes)?)?;
+        Ok(ProgramCode {
+            max_instructions: code.len(),
+            code,
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["program.asm"]
+    }
+}
+
+/// A program that was requested from disk but hasn't been applied yet.
+#[derive(Resource, Debug, Clone)]
+pub struct PendingProgram {
+    pub handle: Handle<ProgramCode>,
+    /// The [`RobotIndex`](super::robots::RobotIndex) of the robot the program is for.
+    pub robot: usize,
+}
+
+/// The file name a robot's program is saved under, without its extension.
+/// The first robot of a level just uses the level's name.
+#[cfg(not(target_family = "wasm"))]
+fn program_name(level_selection: &LevelSelection, robot: usize) -> String {
+    let level = match level_selection {
+        LevelSelection::Indices(LevelIndices { level, .. }) => format!("level_{level}"),
+        other => {
+            tracing::warn!("Expected LevelSelection::Indices, got {:?}", other);
+            "level".to_string()
+        }
+    };
+    match robot {
+        0 => level,
+        robot => format!("{level}_robot_{}", robot + 1),
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn programs_dir() -> std::path::PathBuf {
+    bevy::asset::io::file::FileAssetReader::get_base_path()
+        .join("assets")
+        .join(PROGRAMS_DIR)
+}
+
+#[cfg(not(target_family = "wasm"))]
+pub fn save_program(
+    _: Trigger<Pointer<Click>>,
+    program: SelectedProgram,
+    selected: Res<SelectedRobot>,
+    level_selection: Res<LevelSelection>,
+) {
+    let Some(program_code) = program.get() else {
+        tracing::warn!("No robot selected to save the program of");
+        return;
+    };
+    let path = programs_dir().join(format!(
+        "{}.program.ron",
+        program_name(&level_selection, selected.0)
+    ));
+    let result = ron::ser::to_string_pretty(program_code, ron::ser::PrettyConfig::default())
+        .map_err(|err| err.to_string())
+        .and_then(|contents| {
+            std::fs::create_dir_all(programs_dir())
+                .and_then(|_| std::fs::write(&path, contents))
+                .map_err(|err| err.to_string())
+        });
+    match result {
+        Ok(()) => tracing::info!("Saved program to {}", path.display()),
+        Err(err) => tracing::warn!("Failed to save program to {}: {err}", path.display()),
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+pub fn load_program(
+    _: Trigger<Pointer<Click>>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    selected: Res<SelectedRobot>,
+    level_selection: Res<LevelSelection>,
+) {
+    let name = program_name(&level_selection, selected.0);
+    // Prefer what `save_program` writes, but accept hand-written JSON and
+    // assembly too.
+    let Some(file_name) = [
+        format!("{name}.program.ron"),
+        format!("{name}.program.json"),
+        format!("{name}.program.asm"),
+    ]
+    .into_iter()
+    .find(|file_name| programs_dir().join(file_name).exists()) else {
+        tracing::warn!(
+            "No saved program for {name} in {}",
+            programs_dir().display()
+        );
+        return;
+    };
+    tracing::info!("Loading program {file_name}");
+    commands.insert_resource(PendingProgram {
+        handle: asset_server.load(format!("{PROGRAMS_DIR}/{file_name}")),
+        robot: selected.0,
+    });
+}
+
+fn apply_loaded_program(
+    mut commands: Commands,
+    pending: Res<PendingProgram>,
+    programs: Res<Assets<ProgramCode>>,
+    asset_server: Res<AssetServer>,
+    unlocked_instructions: Res<UnlockedInstructions>,
+    mut editor: ProgramEditor,
+) {
+    let Some(loaded) = programs.get(&pending.handle) else {
+        if asset_server.load_state(&pending.handle).is_failed() {
+            tracing::warn!("Failed to load program {:?}", pending.handle.path());
+            commands.remove_resource::<PendingProgram>();
+        }
+        return;
+    };
+    // Dropping the handle unloads the asset, so the next load reads the file again.
+    commands.remove_resource::<PendingProgram>();
+    let Some(program_code) = editor.program(pending.robot) else {
+        tracing::warn!("Robot {} isn't in this level", pending.robot + 1);
+        return;
+    };
+
+    if let Some(locked) = loaded
+        .code
+        .iter()
+        .find(|instruction| !unlocked_instructions.contains(instruction))
+    {
+        tracing::warn!("Loaded program uses {:?}, which isn't unlocked yet", locked);
+        return;
+    }
+    let mut code = loaded.code.clone();
+    if code.len() > program_code.max_instructions {
+        tracing::warn!(
+            "Loaded program has {} instructions, truncating to {}",
+            code.len(),
+            program_code.max_instructions
+        );
+        code.truncate(program_code.max_instructions);
+    }
+    editor.replace(pending.robot, code);
+}
@@ -0,0 +1,210 @@
+//! Scrubbing backward and forward through a finished run.
+//!
+//! The level is respawned when a run ends, so rewinding only moves the robots'
+//! [`Transform`]s, hides collected gems and exploded crates, and draws the
+//! walls explosions cleared. The robots' [`GridCoords`] stay at the spawn
+//! points, which is where the next run starts from.
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::{GridCoords, utils::grid_coords_to_translation};
+
+use crate::{
+    state::{GameState, ProgramState},
+    theme::interaction::Inactive,
+};
+
+use super::{
+    cpu::CpuState,
+    explosives::{ClearedWall, CrateObject, cleared_wall},
+    level::{Floor, LevelAssets},
+    objects::GemObject,
+    player::{PlayerDirection, PlayerObject},
+    robots::RobotIndex,
+    simulation::{Simulation, Snapshot},
+};
+
+pub(super) fn plugin(app: &mut App) {
+    app.register_type::<RunHistory>()
+        .register_type::<RewindButton>()
+        .register_type::<RewindDisplay>();
+    app.init_resource::<RunHistory>();
+    app.add_systems(OnEnter(ProgramState::Running), reset_rewind_view);
+    app.add_systems(OnExit(GameState::Playing), cleanup_history);
+    app.add_systems(
+        Update,
+        (
+            apply_rewind.run_if(resource_exists_and_changed::<RunHistory>),
+            update_rewind_text.run_if(resource_exists_and_changed::<RunHistory>),
+        )
+            .run_if(in_state(ProgramState::Buying)),
+    );
+}
+
+/// A [`Snapshot`] of the simulation after every tick of the last run.
+#[derive(Resource, Reflect, Debug, Clone, Default)]
+#[reflect(Resource)]
+pub struct RunHistory {
+    /// The first snapshot is taken before the first tick.
+    pub snapshots: Vec<Snapshot>,
+    /// The snapshot being shown, or `None` to show the level as spawned.
+    pub cursor: Option<usize>,
+}
+
+impl RunHistory {
+    pub fn new(initial: Snapshot) -> Self {
+        RunHistory {
+            snapshots: vec![initial],
+            cursor: None,
+        }
+    }
+
+    pub fn record(&mut self, snapshot: Snapshot) {
+        self.snapshots.push(snapshot);
+    }
+
+    /// The number of recorded ticks.
+    pub fn ticks(&self) -> usize {
+        self.snapshots.len().saturating_sub(1)
+    }
+
+    fn step_cursor(&mut self, forward: bool) {
+        if self.snapshots.is_empty() {
+            return;
+        }
+        let cursor = self.cursor.unwrap_or(self.ticks());
+        self.cursor = Some(if forward {
+            (cursor + 1).min(self.ticks())
+        } else {
+            cursor.saturating_sub(1)
+        });
+    }
+}
+
+/// Marks the scrubber buttons, which only work between runs.
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+pub struct RewindButton;
+
+#[derive(Component, Reflect, Debug, Clone, Copy, Default)]
+pub struct RewindDisplay;
+
+pub fn rewind_backward(
+    trigger: Trigger<Pointer<Click>>,
+    inactive: Query<Has<Inactive>>,
+    mut history: ResMut<RunHistory>,
+) {
+    if inactive.get(trigger.target()).unwrap_or_default() {
+        return;
+    }
+    history.step_cursor(false);
+}
+
+pub fn rewind_forward(
+    trigger: Trigger<Pointer<Click>>,
+    inactive: Query<Has<Inactive>>,
+    mut history: ResMut<RunHistory>,
+) {
+    if inactive.get(trigger.target()).unwrap_or_default() {
+        return;
+    }
+    history.step_cursor(true);
+}
+
+fn apply_rewind(
+    mut commands: Commands,
+    history: Res<RunHistory>,
+    simulation: Option<ResMut<Simulation>>,
+    level_assets: Res<LevelAssets>,
+    mut robots: Query<(&RobotIndex, &mut Transform, &mut CpuState)>,
+    mut gems: Query<(&GridCoords, &mut Visibility), (With<GemObject>, Without<CrateObject>)>,
+    mut crates: Query<(&GridCoords, &mut Visibility), With<CrateObject>>,
+    cleared_walls: Query<Entity, With<ClearedWall>>,
+    floors: Query<&ChildOf, With<Floor>>,
+) {
+    let Some(snapshot) = history
+        .cursor
+        .and_then(|cursor| history.snapshots.get(cursor))
+    else {
+        return;
+    };
+    if let Some(mut simulation) = simulation {
+        simulation.restore(snapshot);
+    }
+    for (index, mut transform, mut cpu_state) in &mut robots {
+        let Some(state) = snapshot.robots.get(index.0) else {
+            continue;
+        };
+        transform.translation = grid_coords_to_translation(state.robot.coords, IVec2::splat(18))
+            .extend(transform.translation.z);
+        transform.rotation = state.robot.direction.rotation();
+        *cpu_state = state.cpu.clone();
+    }
+    for (coords, mut visibility) in &mut gems {
+        *visibility = if snapshot.gems.contains(coords) && !snapshot.crates.contains(coords) {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+    for (coords, mut visibility) in &mut crates {
+        *visibility = if snapshot.crates.contains(coords) {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+    for wall in &cleared_walls {
+        commands.entity(wall).despawn();
+    }
+    if let Some(floor_layer) = floors.iter().next() {
+        for coords in snapshot.cleared.iter().copied() {
+            commands.spawn((
+                cleared_wall(coords, &level_assets),
+                ChildOf(floor_layer.parent()),
+            ));
+        }
+    }
+}
+
+fn update_rewind_text(history: Res<RunHistory>, mut text: Query<&mut Text, With<RewindDisplay>>) {
+    let ticks = history.ticks();
+    for mut text in &mut text {
+        **text = format!("Tick {}/{ticks}", history.cursor.unwrap_or(ticks));
+    }
+}
+
+/// Puts everything a rewind moved back where the level spawned it.
+fn reset_rewind_view(
+    mut commands: Commands,
+    mut history: ResMut<RunHistory>,
+    mut player: Query<(&GridCoords, &PlayerDirection, &mut Transform), With<PlayerObject>>,
+    mut gems: Query<(&GridCoords, &mut Visibility), (With<GemObject>, Without<CrateObject>)>,
+    mut crates: Query<(&GridCoords, &mut Visibility), With<CrateObject>>,
+    cleared_walls: Query<Entity, With<ClearedWall>>,
+) {
+    history.cursor = None;
+    for (coords, direction, mut transform) in &mut player {
+        transform.translation =
+            grid_coords_to_translation(*coords, IVec2::splat(18)).extend(transform.translation.z);
+        transform.rotation = direction.rotation();
+    }
+    for (coords, mut visibility) in &mut gems {
+        *visibility = if crates
+            .iter()
+            .any(|(crate_coords, _)| crate_coords == coords)
+        {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+    }
+    for (_, mut visibility) in &mut crates {
+        *visibility = Visibility::Inherited;
+    }
+    for wall in &cleared_walls {
+        commands.entity(wall).despawn();
+    }
+}
+
+fn cleanup_history(mut commands: Commands) {
+    commands.insert_resource(RunHistory::default());
+}
@@ -0,0 +1,200 @@
+//! Every robot in a level owns a [`ProgramCode`] and a [`CpuState`], and the
+//! program panel edits whichever one is selected.
+//!
+//! The level is respawned after every run, so programs are stashed by the
+//! robot's LDtk iid and handed back to the new entity when it appears.
+
+use std::{cmp::Reverse, collections::HashMap};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_ecs_ldtk::{EntityIid, GridCoords};
+
+use crate::state::GameState;
+
+use super::{
+    cpu::{CpuState, Instruction, ProgramCode},
+    level::spawn_level_ui,
+    player::PlayerObject,
+};
+
+pub(super) fn plugin(app: &mut App) {
+    app.register_type::<RobotIndex>()
+        .register_type::<SelectedRobot>()
+        .register_type::<RobotPrograms>()
+        .register_type::<SelectedRobotDisplay>();
+    app.add_systems(
+        OnEnter(GameState::Playing),
+        setup_robot_programs.after(spawn_level_ui),
+    );
+    app.add_systems(OnExit(GameState::Playing), cleanup_robot_programs);
+    app.add_systems(
+        Update,
+        (
+            (attach_robot_programs, assign_robot_indices)
+                .chain()
+                .run_if(resource_exists::<RobotPrograms>),
+            stash_robot_programs.run_if(any_match_filter::<Changed<ProgramCode>>),
+            update_selected_robot_text.run_if(
+                resource_exists_and_changed::<SelectedRobot>
+                    .or(any_match_filter::<Changed<RobotIndex>>),
+            ),
+        )
+            .chain()
+            .run_if(in_state(GameState::Playing)),
+    );
+}
+
+/// A robot's place in the execution order. Robots are numbered in reading
+/// order of where they spawn: top row first, then left to right.
+#[derive(Component, Reflect, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RobotIndex(pub usize);
+
+/// The [`RobotIndex`] of the robot shown in the program panel.
+#[derive(Resource, Reflect, Debug, Clone, Copy, Default)]
+#[reflect(Resource)]
+pub struct SelectedRobot(pub usize);
+
+#[derive(Component, Reflect, Debug, Clone, Copy, Default)]
+pub struct SelectedRobotDisplay;
+
+/// Every robot's program, keyed by LDtk entity iid.
+#[derive(Resource, Reflect, Debug, Clone)]
+#[reflect(Resource)]
+pub struct RobotPrograms {
+    /// What a robot without a stashed program starts with.
+    pub template: ProgramCode,
+    pub programs: HashMap<String, ProgramCode>,
+}
+
+impl Default for RobotPrograms {
+    fn default() -> Self {
+        RobotPrograms {
+            template: ProgramCode {
+                code: vec![Instruction::MoveForward],
+                max_instructions: 1,
+            },
+            programs: HashMap::new(),
+        }
+    }
+}
+
+impl RobotPrograms {
+    /// Runs `edit` on the template and every stashed program.
+    pub fn for_each_mut(&mut self, mut edit: impl FnMut(&mut ProgramCode)) {
+        edit(&mut self.template);
+        self.programs.values_mut().for_each(edit);
+    }
+}
+
+/// The program of the robot selected in the program panel.
+#[derive(SystemParam)]
+pub struct SelectedProgram<'w, 's> {
+    selected: Res<'w, SelectedRobot>,
+    programs: Query<'w, 's, (&'static RobotIndex, &'static ProgramCode)>,
+}
+
+impl SelectedProgram<'_, '_> {
+    pub fn get(&self) -> Option<&ProgramCode> {
+        self.programs
+            .iter()
+            .find(|(index, _)| index.0 == self.selected.0)
+            .map(|(_, program_code)| program_code)
+    }
+}
+
+fn setup_robot_programs(mut commands: Commands) {
+    commands.init_resource::<RobotPrograms>();
+    commands.init_resource::<SelectedRobot>();
+}
+
+fn cleanup_robot_programs(mut commands: Commands) {
+    commands.remove_resource::<RobotPrograms>();
+    commands.remove_resource::<SelectedRobot>();
+}
+
+fn attach_robot_programs(
+    mut commands: Commands,
+    robot_programs: Res<RobotPrograms>,
+    robots: Query<(Entity, &EntityIid), (With<PlayerObject>, Without<ProgramCode>)>,
+) {
+    for (robot, iid) in &robots {
+        let program_code = robot_programs
+            .programs
+            .get(iid.as_str())
+            .unwrap_or(&robot_programs.template)
+            .clone();
+        commands
+            .entity(robot)
+            .insert((program_code, CpuState::default()));
+    }
+}
+
+fn assign_robot_indices(
+    mut commands: Commands,
+    robots: Query<(Entity, &GridCoords, Has<RobotIndex>), With<PlayerObject>>,
+    mut selected: ResMut<SelectedRobot>,
+) {
+    if robots.iter().all(|(_, _, indexed)| indexed) {
+        return;
+    }
+    let mut ordered = robots
+        .iter()
+        .map(|(robot, coords, _)| (robot, *coords))
+        .collect::<Vec<_>>();
+    ordered.sort_by_key(|(_, coords)| (Reverse(coords.y), coords.x));
+    for (index, (robot, _)) in ordered.iter().enumerate() {
+        commands.entity(*robot).insert(RobotIndex(index));
+    }
+    if selected.0 >= ordered.len() {
+        selected.0 = 0;
+    }
+}
+
+fn stash_robot_programs(
+    mut robot_programs: ResMut<RobotPrograms>,
+    robots: Query<(&EntityIid, &ProgramCode), Changed<ProgramCode>>,
+) {
+    for (iid, program_code) in &robots {
+        robot_programs
+            .programs
+            .insert(iid.as_str().to_string(), program_code.clone());
+    }
+}
+
+fn select_robot(selected: &mut SelectedRobot, robots: usize, forward: bool) {
+    if robots == 0 {
+        return;
+    }
+    selected.0 = if forward {
+        (selected.0 + 1) % robots
+    } else {
+        (selected.0 + robots - 1) % robots
+    };
+    tracing::info!("Selected robot {}", selected.0 + 1);
+}
+
+pub fn select_previous_robot(
+    _: Trigger<Pointer<Click>>,
+    mut selected: ResMut<SelectedRobot>,
+    robots: Query<(), With<RobotIndex>>,
+) {
+    select_robot(&mut selected, robots.iter().len(), false);
+}
+
+pub fn select_next_robot(
+    _: Trigger<Pointer<Click>>,
+    mut selected: ResMut<SelectedRobot>,
+    robots: Query<(), With<RobotIndex>>,
+) {
+    select_robot(&mut selected, robots.iter().len(), true);
+}
+
+fn update_selected_robot_text(
+    selected: Res<SelectedRobot>,
+    robots: Query<(), With<RobotIndex>>,
+    mut text: Query<&mut Text, With<SelectedRobotDisplay>>,
+) {
+    for mut text in &mut text {
+        **text = format!("Robot {}/{}", selected.0 + 1, robots.iter().len());
+    }
+}
@@ -0,0 +1,269 @@
+//! Scoring completed runs, and keeping the best of them per level in a local
+//! leaderboard file.
+
+use std::{cmp::Reverse, collections::BTreeMap, fmt, time::Duration};
+
+use bevy::{color::palettes::css::*, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{state::GameState, theme::widget};
+
+use super::{
+    cpu::CpuOptions,
+    objects::{BombTimer, LevelCompleted},
+    rewind::RunHistory,
+    simulation::Simulation,
+};
+
+/// The file the leaderboard is kept in, next to the assets folder.
+#[cfg(not(target_family = "wasm"))]
+const LEADERBOARD_FILE: &str = "leaderboard.ron";
+
+/// How many runs each leaderboard of a level keeps.
+const LEADERBOARD_SIZE: usize = 5;
+
+pub(super) fn plugin(app: &mut App) {
+    app.register_type::<Leaderboard>()
+        .register_type::<RunScore>()
+        .register_type::<ResultsPanel>();
+    app.add_systems(Startup, load_leaderboard);
+    #[cfg(not(target_family = "wasm"))]
+    app.add_systems(
+        Update,
+        save_leaderboard.run_if(
+            resource_exists_and_changed::<Leaderboard>.and(not(resource_added::<Leaderboard>)),
+        ),
+    );
+    app.add_observer(record_score);
+}
+
+/// How well a run that completed its level did.
+#[derive(Reflect, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunScore {
+    /// Instructions in every robot's program together.
+    pub instructions: usize,
+    pub ticks: usize,
+    /// What was left on the [`BombTimer`], in game time.
+    pub time_left: Duration,
+}
+
+impl RunScore {
+    /// Orders runs by fewest instructions, then fewest ticks.
+    fn shortest(&self) -> (usize, usize, Reverse<Duration>) {
+        (self.instructions, self.ticks, Reverse(self.time_left))
+    }
+
+    /// Orders runs by fewest ticks, then fewest instructions.
+    fn fastest(&self) -> (usize, usize, Reverse<Duration>) {
+        (self.ticks, self.instructions, Reverse(self.time_left))
+    }
+}
+
+impl fmt::Display for RunScore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} instructions, {} ticks, {:.1?} left",
+            self.instructions, self.ticks, self.time_left
+        )
+    }
+}
+
+/// The best runs of every level, by level index.
+#[derive(Resource, Reflect, Serialize, Deserialize, Debug, Clone, Default)]
+#[reflect(Resource)]
+pub struct Leaderboard {
+    pub levels: BTreeMap<usize, Vec<RunScore>>,
+}
+
+impl Leaderboard {
+    /// The shortest runs of `level`, best first.
+    pub fn shortest(&self, level: usize) -> Vec<RunScore> {
+        self.ranked(level, RunScore::shortest)
+    }
+
+    /// The fastest runs of `level`, best first.
+    pub fn fastest(&self, level: usize) -> Vec<RunScore> {
+        self.ranked(level, RunScore::fastest)
+    }
+
+    fn ranked<K: Ord>(&self, level: usize, key: fn(&RunScore) -> K) -> Vec<RunScore> {
+        let mut scores = self.levels.get(&level).cloned().unwrap_or_default();
+        scores.sort_by_key(key);
+        scores.truncate(LEADERBOARD_SIZE);
+        scores
+    }
+
+    /// Adds `score` to the runs of `level`, keeping only runs that make it
+    /// onto either leaderboard.
+    pub fn record(&mut self, level: usize, score: RunScore) {
+        let scores = self.levels.entry(level).or_default();
+        if !scores.contains(&score) {
+            scores.push(score);
+        }
+        let shortest = self.shortest(level);
+        let fastest = self.fastest(level);
+        if let Some(scores) = self.levels.get_mut(&level) {
+            scores.retain(|score| shortest.contains(score) || fastest.contains(score));
+        }
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn leaderboard_path() -> std::path::PathBuf {
+    bevy::asset::io::file::FileAssetReader::get_base_path().join(LEADERBOARD_FILE)
+}
+
+fn load_leaderboard(mut commands: Commands) {
+    #[cfg(not(target_family = "wasm"))]
+    {
+        let path = leaderboard_path();
+        if let Ok(contents) = std::fs::read_to_string(&path) {
+            match ron::from_str::<Leaderboard>(&contents) {
+                Ok(leaderboard) => {
+                    tracing::info!("Loaded leaderboard from {}", path.display());
+                    commands.insert_resource(leaderboard);
+                    return;
+                }
+                Err(err) => tracing::warn!("Failed to read {}: {err}", path.display()),
+            }
+        }
+    }
+    commands.init_resource::<Leaderboard>();
+}
+
+/// Writes the leaderboard back to its file whenever a run changes it.
+#[cfg(not(target_family = "wasm"))]
+fn save_leaderboard(leaderboard: Res<Leaderboard>) {
+    let path = leaderboard_path();
+    let result = ron::ser::to_string_pretty(&*leaderboard, ron::ser::PrettyConfig::default())
+        .map_err(|err| err.to_string())
+        .and_then(|contents| std::fs::write(&path, contents).map_err(|err| err.to_string()));
+    if let Err(err) = result {
+        tracing::warn!("Failed to save leaderboard to {}: {err}", path.display());
+    }
+}
+
+/// The panel showing how the last run scored.
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+pub struct ResultsPanel;
+
+fn record_score(
+    trigger: Trigger<LevelCompleted>,
+    mut commands: Commands,
+    simulation: Option<Res<Simulation>>,
+    history: Res<RunHistory>,
+    bomb_timer: Option<Res<BombTimer>>,
+    cpu_options: Res<CpuOptions>,
+    mut leaderboard: ResMut<Leaderboard>,
+    old_panels: Query<Entity, With<ResultsPanel>>,
+) {
+    let level = trigger.event().level;
+    let score = RunScore {
+        instructions: simulation
+            .map(|simulation| simulation.programs.iter().map(Vec::len).sum())
+            .unwrap_or_default(),
+        ticks: history.ticks(),
+        time_left: bomb_timer
+            .map(|bomb_timer| bomb_timer.timer.remaining().div_f32(cpu_options.multiplier))
+            .unwrap_or_default(),
+    };
+    tracing::info!("Level {} completed: {score}", level + 1);
+    leaderboard.record(level, score);
+
+    for panel in &old_panels {
+        commands.entity(panel).despawn();
+    }
+    commands.spawn(results_panel(level, score, &leaderboard));
+}
+
+fn results_panel(level: usize, score: RunScore, leaderboard: &Leaderboard) -> impl Bundle {
+    let ranking = |title: &str, scores: Vec<RunScore>| {
+        let mut lines = vec![title.to_string()];
+        lines.extend(
+            scores
+                .iter()
+                .enumerate()
+                .map(|(rank, ranked)| format!("{}. {ranked}", rank + 1)),
+        );
+        lines.join("\n")
+    };
+    (
+        widget::ui_root("Level Results"),
+        GlobalZIndex(1),
+        ResultsPanel,
+        StateScoped(GameState::Playing),
+        children![(
+            Name::new("Results Panel"),
+            Node {
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(10.0),
+                padding: UiRect::all(Val::Px(20.0)),
+                ..default()
+            },
+            BackgroundColor(Color::BLACK.with_alpha(0.85)),
+            Outline {
+                width: Val::Px(2.0),
+                offset: Val::Px(0.0),
+                color: ROSY_BROWN.into(),
+            },
+            BorderRadius::all(Val::Px(5.0)),
+            children![
+                widget::header(format!("Level {} complete!", level + 1)),
+                widget::colored_label(score.to_string(), GOLD.into()),
+                widget::label(ranking("Shortest", leaderboard.shortest(level))),
+                widget::label(ranking("Fastest", leaderboard.fastest(level))),
+                widget::button_medium("Continue", close_results, ()),
+            ],
+        )],
+    )
+}
+
+fn close_results(
+    _: Trigger<Pointer<Click>>,
+    mut commands: Commands,
+    panels: Query<Entity, With<ResultsPanel>>,
+) {
+    for panel in &panels {
+        commands.entity(panel).despawn();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs_ldtk::LevelSelection;
+
+    use super::*;
+    use crate::{
+        game::objects::{AllGemsCollected, all_gems_collected},
+        state::ProgramState,
+    };
+
+    #[test]
+    fn each_completion_records_its_own_level() {
+        let mut world = World::new();
+        world.insert_resource(LevelSelection::index(0));
+        world.insert_resource(NextState::<ProgramState>::default());
+        world.insert_resource(CpuOptions::default());
+        world.init_resource::<RunHistory>();
+        world.init_resource::<Leaderboard>();
+        world.add_observer(all_gems_collected);
+        world.add_observer(record_score);
+
+        world.trigger(AllGemsCollected);
+        world.flush();
+        world.trigger(AllGemsCollected);
+        world.flush();
+
+        let leaderboard = world.resource::<Leaderboard>();
+        assert_eq!(
+            leaderboard.levels.keys().copied().collect::<Vec<_>>(),
+            [0, 1]
+        );
+        assert_eq!(
+            *world.resource::<LevelSelection>(),
+            LevelSelection::index(2)
+        );
+    }
+}
@@ -0,0 +1,702 @@
+//! The robot CPU as plain data. A [`Simulation`] knows nothing about entities,
+//! assets or rendering, so levels and programs can be run, benchmarked and
+//! solved without a Bevy world. While a program runs, the observers in
+//! [`super::cpu`] step it once per [`Tick`](super::ticks::Tick) and mirror the
+//! reported [`Effect`]s onto the spawned entities.
+
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::GridCoords;
+
+use super::{
+    cpu::{Action, CpuOptions, CpuState, Instruction, Interrupt, Sensor},
+    level::LevelGrid,
+    player::PlayerDirection,
+};
+
+/// The cells an explosion reaches, around the crate that exploded.
+const NEIGHBORS: [GridCoords; 4] = [
+    GridCoords { x: 0, y: 1 },
+    GridCoords { x: 1, y: 0 },
+    GridCoords { x: 0, y: -1 },
+    GridCoords { x: -1, y: 0 },
+];
+
+pub(super) fn plugin(app: &mut App) {
+    app.register_type::<Simulation>()
+        .register_type::<Robot>()
+        .register_type::<RobotState>()
+        .register_type::<StopReason>()
+        .register_type::<Snapshot>();
+}
+
+#[derive(Reflect, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Robot {
+    pub coords: GridCoords,
+    pub direction: PlayerDirection,
+}
+
+impl Robot {
+    /// The cell the robot is facing.
+    pub fn ahead(&self) -> GridCoords {
+        self.coords + self.direction.0
+    }
+}
+
+/// A robot and the CPU running its program.
+#[derive(Reflect, Debug, Clone)]
+pub struct RobotState {
+    pub robot: Robot,
+    pub cpu: CpuState,
+    /// Why this robot's program stopped, if it has.
+    pub stopped: Option<StopReason>,
+}
+
+/// Everything a program can observe or change while it runs.
+#[derive(Resource, Reflect, Debug, Clone)]
+#[reflect(Resource)]
+pub struct Simulation {
+    pub grid: LevelGrid,
+    /// Gems under a crate only show up once the crate is blown away.
+    pub gems: HashSet<GridCoords>,
+    /// Crates that haven't exploded yet. They block robots like walls do.
+    pub crates: HashSet<GridCoords>,
+    /// Crates that explode on the next tick.
+    pub fuses: HashSet<GridCoords>,
+    /// Walls that explosions turned into floor.
+    pub cleared: HashSet<GridCoords>,
+    /// Every robot in the level, in the order they execute each tick.
+    pub robots: Vec<RobotState>,
+    /// The program of each robot in `robots`.
+    pub programs: Vec<Vec<Instruction>>,
+}
+
+/// The parts of a [`Simulation`] a program can change, as of one tick.
+#[derive(Reflect, Debug, Clone)]
+pub struct Snapshot {
+    pub robots: Vec<RobotState>,
+    pub gems: HashSet<GridCoords>,
+    pub crates: HashSet<GridCoords>,
+    pub fuses: HashSet<GridCoords>,
+    pub cleared: HashSet<GridCoords>,
+}
+
+/// A change to the world caused by a single [`Simulation::step`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Effect {
+    Moved {
+        from: GridCoords,
+        to: GridCoords,
+    },
+    /// The robot tried to move onto a cell that isn't floor, or that another
+    /// robot is standing on.
+    Bumped {
+        wall: GridCoords,
+    },
+    Turned {
+        direction: PlayerDirection,
+    },
+    GemCollected {
+        coords: GridCoords,
+    },
+    /// The robot jumped to the handler of an interrupt.
+    Interrupted {
+        interrupt: Interrupt,
+    },
+    /// The robot lit the fuse of the crate ahead. It explodes next tick.
+    Detonated {
+        coords: GridCoords,
+    },
+}
+
+/// Why a program stopped running.
+#[derive(Reflect, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    EndOfProgram,
+    Halted,
+    /// A [`Instruction::Return`] ran with an empty call stack.
+    ReturnedFromTop,
+    /// A [`Instruction::Call`] would nest deeper than
+    /// [`CpuOptions::max_call_depth`].
+    StackOverflow,
+    AllGemsCollected,
+}
+
+/// What happened to one robot during a single [`Simulation::step`].
+#[derive(Debug, Clone, Default)]
+pub struct RobotStep {
+    /// The slot and instruction that completed, if any. Instructions costing
+    /// more than one cycle only complete on their last tick.
+    pub executed: Option<(usize, Instruction)>,
+    pub effects: Vec<Effect>,
+    /// Set on the tick this robot's program stopped.
+    pub stopped: Option<StopReason>,
+}
+
+/// What happened during a single [`Simulation::step`].
+#[derive(Debug, Clone, Default)]
+pub struct StepReport {
+    /// One entry per robot, in the same order as [`Simulation::robots`].
+    pub robots: Vec<RobotStep>,
+    /// Crates that exploded at the start of the tick.
+    pub exploded: Vec<GridCoords>,
+    /// Walls those explosions cleared.
+    pub cleared: Vec<GridCoords>,
+    /// Set on the tick the whole run ended.
+    pub stopped: Option<StopReason>,
+}
+
+impl Simulation {
+    pub fn new(grid: LevelGrid, gems: HashSet<GridCoords>, crates: HashSet<GridCoords>) -> Self {
+        Simulation {
+            grid,
+            gems,
+            crates,
+            fuses: HashSet::new(),
+            cleared: HashSet::new(),
+            robots: vec![],
+            programs: vec![],
+        }
+    }
+
+    /// Adds a robot that executes after every robot added before it.
+    pub fn add_robot(&mut self, robot: Robot, code: Vec<Instruction>) {
+        self.robots.push(RobotState {
+            robot,
+            cpu: CpuState::default(),
+            stopped: None,
+        });
+        self.programs.push(code);
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            robots: self.robots.clone(),
+            gems: self.gems.clone(),
+            crates: self.crates.clone(),
+            fuses: self.fuses.clone(),
+            cleared: self.cleared.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.robots = snapshot.robots.clone();
+        self.gems = snapshot.gems.clone();
+        self.crates = snapshot.crates.clone();
+        self.fuses = snapshot.fuses.clone();
+        self.cleared = snapshot.cleared.clone();
+    }
+
+    /// Whether `coords` is floor, either from the level or blown open.
+    pub fn is_floor(&self, coords: GridCoords) -> bool {
+        self.grid.is_floor(coords) || self.cleared.contains(&coords)
+    }
+
+    /// Whether a robot could move onto `coords`.
+    pub fn is_free(&self, coords: GridCoords) -> bool {
+        self.is_floor(coords)
+            && !self.crates.contains(&coords)
+            && self.robots.iter().all(|state| state.robot.coords != coords)
+    }
+
+    /// Whether the cell ahead of the robot at `index` is taken.
+    pub fn is_blocked(&self, index: usize) -> bool {
+        !self.is_free(self.robots[index].robot.ahead())
+    }
+
+    /// Whether `sensor` reads true for the robot at `index`.
+    pub fn holds(&self, index: usize, sensor: Sensor) -> bool {
+        let robot = &self.robots[index].robot;
+        let facing = |x: i32, y: i32| robot.direction.0 == GridCoords::new(x, y);
+        match sensor {
+            Sensor::WallAhead => self.is_blocked(index),
+            Sensor::WallLeft => !self.is_free(robot.coords + robot.direction.left()),
+            Sensor::WallRight => !self.is_free(robot.coords + robot.direction.right()),
+            Sensor::GemAhead => {
+                self.gems.contains(&robot.ahead()) && !self.crates.contains(&robot.ahead())
+            }
+            Sensor::FacingNorth => facing(0, 1),
+            Sensor::FacingEast => facing(1, 0),
+            Sensor::FacingSouth => facing(0, -1),
+            Sensor::FacingWest => facing(-1, 0),
+        }
+    }
+
+    /// Fires `interrupt` for the robot at `index`. It's ignored unless the
+    /// program registered a handler for it.
+    pub fn raise(&mut self, index: usize, interrupt: Interrupt) {
+        let cpu = &mut self.robots[index].cpu;
+        if cpu.handlers[interrupt.index()].is_some() && !cpu.pending.contains(&interrupt) {
+            cpu.pending.push(interrupt);
+        }
+    }
+
+    /// Fires `interrupt` for every robot.
+    pub fn raise_all(&mut self, interrupt: Interrupt) {
+        for index in 0..self.robots.len() {
+            self.raise(index, interrupt);
+        }
+    }
+
+    /// Explodes every lit crate, lighting the crates next to it for the next
+    /// tick and clearing the walls around it.
+    fn explode(&mut self, report: &mut StepReport) {
+        let exploding = std::mem::take(&mut self.fuses);
+        for coords in &exploding {
+            self.crates.remove(coords);
+        }
+        for coords in exploding {
+            report.exploded.push(coords);
+            for direction in NEIGHBORS {
+                let neighbor = coords + direction;
+                if self.crates.contains(&neighbor) {
+                    self.fuses.insert(neighbor);
+                } else if !self.is_floor(neighbor) && self.grid.in_bounds(neighbor) {
+                    self.cleared.insert(neighbor);
+                    report.cleared.push(neighbor);
+                }
+            }
+        }
+    }
+
+    /// Advances every robot that is still running by one tick, in order.
+    pub fn step(&mut self, options: &CpuOptions) -> StepReport {
+        let mut report = StepReport::default();
+        let mut last_stop = None;
+        self.explode(&mut report);
+        for index in 0..self.robots.len() {
+            if self.robots[index].stopped.is_some() {
+                report.robots.push(RobotStep::default());
+                continue;
+            }
+            let step = self.step_robot(index, options);
+            if let Some(reason) = step.stopped {
+                self.robots[index].stopped = Some(reason);
+                last_stop = Some(reason);
+            }
+            let collected = step
+                .effects
+                .iter()
+                .any(|effect| matches!(effect, Effect::GemCollected { .. }));
+            report.robots.push(step);
+            if collected && self.gems.is_empty() {
+                report
+                    .robots
+                    .resize_with(self.robots.len(), RobotStep::default);
+                report.stopped = Some(StopReason::AllGemsCollected);
+                return report;
+            }
+        }
+
+        if self.robots.iter().all(|state| state.stopped.is_some()) {
+            report.stopped = Some(last_stop.unwrap_or(StopReason::EndOfProgram));
+        }
+        report
+    }
+
+    fn step_robot(&mut self, index: usize, options: &CpuOptions) -> RobotStep {
+        let mut step = RobotStep::default();
+
+        // Interrupts are only taken between instructions, one handler at a time.
+        let cpu = &mut self.robots[index].cpu;
+        if cpu.busy_cycles == 0 && cpu.handler_depth.is_none() && !cpu.pending.is_empty() {
+            let interrupt = cpu.pending.remove(0);
+            if cpu.call_stack.len() >= options.max_call_depth {
+                step.stopped = Some(StopReason::StackOverflow);
+                return step;
+            }
+            if let Some(handler) = cpu.handlers[interrupt.index()] {
+                cpu.handler_depth = Some(cpu.call_stack.len());
+                cpu.call_stack.push(cpu.pc);
+                cpu.pc = handler;
+                step.effects.push(Effect::Interrupted { interrupt });
+            }
+        }
+
+        let cpu = &mut self.robots[index].cpu;
+        let len = self.programs[index].len();
+        if options.loop_program && len > 0 && cpu.pc >= len {
+            cpu.pc = 0;
+            cpu.loops += 1;
+        }
+        let pc = cpu.pc;
+        let Some(instruction) = self.programs[index].get(pc).copied() else {
+            step.stopped = Some(StopReason::EndOfProgram);
+            return step;
+        };
+
+        // Slow instructions only take effect on their last cycle.
+        let cpu = &mut self.robots[index].cpu;
+        if cpu.busy_cycles == 0 {
+            cpu.busy_cycles = options.cycle_costs.cost(&instruction);
+        }
+        cpu.busy_cycles -= 1;
+        if cpu.busy_cycles > 0 {
+            return step;
+        }
+        step.executed = Some((pc, instruction));
+        cpu.pc += 1;
+
+        let blocked = self.is_blocked(index);
+        let state = &self.robots[index];
+        let left = state.robot.direction.left();
+        let gap_on_left = self.is_free(state.robot.coords + left);
+        let right = state.robot.direction.right();
+        let sensed = instruction
+            .sensor()
+            .is_some_and(|sensor| self.holds(index, sensor));
+
+        let cpu = &mut self.robots[index].cpu;
+        match instruction {
+            Instruction::MoveForward => self.move_forward(index, &mut step),
+            Instruction::IfGapTurnLeft => {
+                if gap_on_left {
+                    self.turn(index, left, &mut step);
+                }
+            }
+            Instruction::IfWallTurnRight => {
+                if blocked {
+                    self.turn(index, right, &mut step);
+                }
+            }
+            Instruction::Jump(target) => {
+                cpu.pc = target;
+            }
+            Instruction::JumpIfBlocked(target) if blocked => {
+                cpu.pc = target;
+            }
+            Instruction::Halt => {
+                step.stopped = Some(StopReason::Halted);
+            }
+            Instruction::Set(register, value) => {
+                cpu.registers[register.index()] = value;
+            }
+            Instruction::Inc(register) => {
+                let value = &mut cpu.registers[register.index()];
+                *value = value.wrapping_add(1);
+            }
+            Instruction::Dec(register) => {
+                let value = &mut cpu.registers[register.index()];
+                *value = value.wrapping_sub(1);
+            }
+            Instruction::JumpIfZero(register, target) if cpu.registers[register.index()] == 0 => {
+                cpu.pc = target;
+            }
+            Instruction::Call(target) => {
+                if cpu.call_stack.len() >= options.max_call_depth {
+                    step.stopped = Some(StopReason::StackOverflow);
+                } else {
+                    cpu.call_stack.push(cpu.pc);
+                    cpu.pc = target;
+                }
+            }
+            Instruction::Return => match cpu.call_stack.pop() {
+                Some(return_address) => {
+                    cpu.pc = return_address;
+                    if cpu.handler_depth == Some(cpu.call_stack.len()) {
+                        cpu.handler_depth = None;
+                    }
+                }
+                None => step.stopped = Some(StopReason::ReturnedFromTop),
+            },
+            Instruction::OnInterrupt(interrupt, target) => {
+                cpu.handlers[interrupt.index()] = Some(target);
+            }
+            Instruction::Detonate => {
+                let ahead = self.robots[index].robot.ahead();
+                if self.crates.contains(&ahead) {
+                    self.fuses.insert(ahead);
+                    step.effects.push(Effect::Detonated { coords: ahead });
+                }
+            }
+            Instruction::If(_, action) if sensed => match action {
+                Action::MoveForward => self.move_forward(index, &mut step),
+                Action::TurnLeft => self.turn(index, left, &mut step),
+                Action::TurnRight => self.turn(index, right, &mut step),
+                Action::Jump(target) => cpu.pc = target,
+            },
+            Instruction::JumpIfBlocked(_) | Instruction::JumpIfZero(..) | Instruction::If(..) => {}
+        }
+        step
+    }
+
+    fn move_forward(&mut self, index: usize, step: &mut RobotStep) {
+        let robot = &self.robots[index].robot;
+        let from = robot.coords;
+        let to = robot.ahead();
+        if !self.is_free(to) {
+            step.effects.push(Effect::Bumped { wall: to });
+            self.raise(index, Interrupt::Bumped);
+            return;
+        }
+        self.robots[index].robot.coords = to;
+        step.effects.push(Effect::Moved { from, to });
+
+        if self.gems.remove(&to) {
+            step.effects.push(Effect::GemCollected { coords: to });
+            self.raise(index, Interrupt::GemCollected);
+        }
+    }
+
+    fn turn(&mut self, index: usize, direction: GridCoords, step: &mut RobotStep) {
+        let robot = &mut self.robots[index].robot;
+        robot.direction = PlayerDirection(direction);
+        step.effects.push(Effect::Turned {
+            direction: robot.direction,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Duration};
+
+    use super::*;
+    use crate::game::cpu::{Action, CycleCosts, Interrupt, Register, Sensor};
+
+    const NORTH: PlayerDirection = PlayerDirection(GridCoords { x: 0, y: 1 });
+    const EAST: PlayerDirection = PlayerDirection(GridCoords { x: 1, y: 0 });
+
+    /// Options where every instruction takes a single cycle.
+    fn options() -> CpuOptions {
+        CpuOptions {
+            cpu_tick: Duration::from_millis(100),
+            multiplier: 1.0,
+            max_call_depth: 4,
+            cycle_costs: CycleCosts(HashMap::new()),
+            loop_program: false,
+        }
+    }
+
+    /// A corridor of floor running east from the origin, with one robot on
+    /// its west end running `code`.
+    fn corridor(length: i32, direction: PlayerDirection, code: Vec<Instruction>) -> Simulation {
+        let grid = LevelGrid {
+            floors: (0..length).map(|x| GridCoords::new(x, 0)).collect(),
+        };
+        let mut simulation = Simulation::new(grid, HashSet::new(), HashSet::new());
+        simulation.add_robot(
+            Robot {
+                coords: GridCoords::new(0, 0),
+                direction,
+            },
+            code,
+        );
+        simulation
+    }
+
+    #[test]
+    fn moving_into_a_wall_bumps() {
+        let mut simulation = corridor(2, NORTH, vec![Instruction::MoveForward]);
+        let report = simulation.step(&options());
+        assert_eq!(
+            report.robots[0].effects,
+            vec![Effect::Bumped {
+                wall: GridCoords::new(0, 1)
+            }]
+        );
+        assert_eq!(simulation.robots[0].robot.coords, GridCoords::new(0, 0));
+    }
+
+    #[test]
+    fn robots_bump_into_each_other_and_stop_together() {
+        let mut simulation = corridor(3, EAST, vec![Instruction::MoveForward]);
+        simulation.add_robot(
+            Robot {
+                coords: GridCoords::new(1, 0),
+                direction: EAST,
+            },
+            vec![Instruction::Halt],
+        );
+        let report = simulation.step(&options());
+        assert_eq!(
+            report.robots[0].effects,
+            vec![Effect::Bumped {
+                wall: GridCoords::new(1, 0)
+            }]
+        );
+        assert_eq!(report.robots[1].stopped, Some(StopReason::Halted));
+        assert_eq!(report.stopped, None);
+        assert_eq!(
+            simulation.step(&options()).stopped,
+            Some(StopReason::EndOfProgram)
+        );
+    }
+
+    #[test]
+    fn slow_move_completes_on_its_last_cycle() {
+        let mut options = options();
+        options.cycle_costs.insert("move_forward".to_string(), 3);
+        let mut simulation = corridor(2, EAST, vec![Instruction::MoveForward]);
+        for _ in 0..2 {
+            let report = simulation.step(&options);
+            assert!(report.robots[0].executed.is_none());
+            assert_eq!(simulation.robots[0].robot.coords, GridCoords::new(0, 0));
+        }
+        let report = simulation.step(&options);
+        assert!(matches!(
+            report.robots[0].executed,
+            Some((0, Instruction::MoveForward))
+        ));
+        assert_eq!(simulation.robots[0].robot.coords, GridCoords::new(1, 0));
+    }
+
+    #[test]
+    fn conditional_acts_only_when_its_sensor_fires() {
+        let options = options();
+        let mut simulation = corridor(
+            2,
+            EAST,
+            vec![
+                Instruction::If(Sensor::WallAhead, Action::TurnLeft),
+                Instruction::MoveForward,
+                Instruction::If(Sensor::WallAhead, Action::Jump(0)),
+            ],
+        );
+        let report = simulation.step(&options);
+        assert!(report.robots[0].effects.is_empty());
+        simulation.step(&options);
+        simulation.step(&options);
+        assert_eq!(simulation.robots[0].cpu.pc, 0);
+        let report = simulation.step(&options);
+        assert_eq!(
+            report.robots[0].effects,
+            vec![Effect::Turned { direction: NORTH }]
+        );
+    }
+
+    #[test]
+    fn detonated_crates_explode_in_a_chain() {
+        let options = options();
+        let mut simulation = corridor(
+            3,
+            EAST,
+            vec![
+                Instruction::Detonate,
+                Instruction::Inc(Register::R0),
+                Instruction::Inc(Register::R0),
+            ],
+        );
+        simulation.crates = [GridCoords::new(1, 0), GridCoords::new(2, 0)].into();
+        simulation.gems = [GridCoords::new(2, 0)].into();
+
+        let report = simulation.step(&options);
+        assert_eq!(
+            report.robots[0].effects,
+            vec![Effect::Detonated {
+                coords: GridCoords::new(1, 0)
+            }]
+        );
+        let report = simulation.step(&options);
+        assert_eq!(report.exploded, vec![GridCoords::new(1, 0)]);
+        assert!(report.cleared.is_empty());
+        assert_eq!(simulation.fuses, [GridCoords::new(2, 0)].into());
+
+        let report = simulation.step(&options);
+        assert_eq!(report.exploded, vec![GridCoords::new(2, 0)]);
+        assert!(simulation.crates.is_empty());
+        assert!(simulation.gems.contains(&GridCoords::new(2, 0)));
+    }
+
+    #[test]
+    fn recursive_call_overflows_the_stack() {
+        let options = options();
+        let mut simulation = corridor(1, EAST, vec![Instruction::Call(0)]);
+        for depth in 1..=options.max_call_depth {
+            let report = simulation.step(&options);
+            assert_eq!(report.stopped, None);
+            assert_eq!(simulation.robots[0].cpu.call_stack.len(), depth);
+        }
+        let report = simulation.step(&options);
+        assert_eq!(report.stopped, Some(StopReason::StackOverflow));
+    }
+
+    #[test]
+    fn return_resumes_after_the_call() {
+        let options = options();
+        let mut simulation = corridor(
+            1,
+            EAST,
+            vec![
+                Instruction::Call(3),
+                Instruction::Inc(Register::R0),
+                Instruction::Halt,
+                Instruction::Return,
+            ],
+        );
+        simulation.step(&options);
+        assert_eq!(simulation.robots[0].cpu.pc, 3);
+        simulation.step(&options);
+        assert_eq!(simulation.robots[0].cpu.pc, 1);
+        assert!(simulation.robots[0].cpu.call_stack.is_empty());
+        simulation.step(&options);
+        assert_eq!(simulation.robots[0].cpu.registers[Register::R0.index()], 1);
+    }
+
+    #[test]
+    fn return_from_top_stops() {
+        let mut simulation = corridor(1, EAST, vec![Instruction::Return]);
+        let report = simulation.step(&options());
+        assert_eq!(report.stopped, Some(StopReason::ReturnedFromTop));
+    }
+
+    #[test]
+    fn interrupt_runs_its_handler_and_returns() {
+        let mut simulation = corridor(
+            1,
+            EAST,
+            vec![
+                Instruction::OnInterrupt(Interrupt::Bumped, 3),
+                Instruction::MoveForward,
+                Instruction::Halt,
+                Instruction::Inc(Register::R0),
+                Instruction::Return,
+            ],
+        );
+        let options = options();
+        simulation.step(&options);
+        simulation.step(&options);
+        assert_eq!(simulation.robots[0].cpu.pending, vec![Interrupt::Bumped]);
+
+        let report = simulation.step(&options);
+        assert!(report.robots[0].effects.contains(&Effect::Interrupted {
+            interrupt: Interrupt::Bumped
+        }));
+        assert!(matches!(
+            report.robots[0].executed,
+            Some((3, Instruction::Inc(Register::R0)))
+        ));
+        assert_eq!(simulation.robots[0].cpu.handler_depth, Some(0));
+
+        simulation.step(&options);
+        assert_eq!(simulation.robots[0].cpu.pc, 2);
+        assert_eq!(simulation.robots[0].cpu.handler_depth, None);
+        let report = simulation.step(&options);
+        assert_eq!(report.stopped, Some(StopReason::Halted));
+        assert_eq!(simulation.robots[0].cpu.registers[Register::R0.index()], 1);
+    }
+
+    #[test]
+    fn looping_program_wraps_to_the_first_slot() {
+        let mut options = options();
+        options.loop_program = true;
+        let mut simulation = corridor(1, EAST, vec![Instruction::Inc(Register::R0)]);
+        for _ in 0..3 {
+            assert_eq!(simulation.step(&options).stopped, None);
+        }
+        assert_eq!(simulation.robots[0].cpu.loops, 2);
+        assert_eq!(simulation.robots[0].cpu.registers[Register::R0.index()], 3);
+    }
+
+    #[test]
+    fn program_without_loop_ends() {
+        let options = options();
+        let mut simulation = corridor(1, EAST, vec![Instruction::Inc(Register::R0)]);
+        assert_eq!(simulation.step(&options).stopped, None);
+        assert_eq!(
+            simulation.step(&options).stopped,
+            Some(StopReason::EndOfProgram)
+        );
+    }
+}
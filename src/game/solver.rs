@@ -0,0 +1,412 @@
+//! Brute-force search for the shortest program that clears a level.
+//!
+//! Every robot gets the same program, built only from the instructions the
+//! player has unlocked. Programs are tried shortest first, so the first one
+//! that collects every gem before the bomb goes off is a par for the level.
+//!
+//! The search can try hundreds of thousands of programs, so it runs on the
+//! [`AsyncComputeTaskPool`] and its result is picked up once it's done.
+
+use std::{fmt, time::Duration};
+
+use bevy::{
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, Task, block_on, futures_lite::future},
+};
+use bevy_ecs_ldtk::GridCoords;
+use itertools::Itertools;
+
+use crate::state::GameState;
+
+use super::{
+    analysis::{Warning, analyze},
+    assembly::format_program,
+    cpu::{Action, CpuOptions, Instruction, Interrupt, Register, Sensor, UnlockedInstructions},
+    editor::ProgramEditor,
+    explosives::CrateObject,
+    level::LevelGrid,
+    objects::{BOMB_LOW_THRESHOLD, GemObject, TimeToBomb},
+    player::PlayerDirection,
+    robots::RobotIndex,
+    simulation::{Robot, Simulation, StopReason},
+};
+
+pub(super) fn plugin(app: &mut App) {
+    app.register_type::<SolveLevel>();
+    app.add_systems(
+        Update,
+        finish_solving
+            .run_if(any_with_component::<SolverTask>)
+            .run_if(in_state(GameState::Playing)),
+    );
+    app.add_observer(solve_level);
+}
+
+/// The largest value the solver tries in an [`Instruction::Set`].
+const MAX_SOLVER_VALUE: i32 = 3;
+
+/// How many programs the solver runs before giving up.
+const MAX_PROGRAMS: usize = 200_000;
+
+/// Searches for the shortest program for the current level.
+#[derive(Event, Reflect, Debug, Clone, Copy)]
+pub struct SolveLevel {
+    /// Whether to replace every robot's program with the solution, or only
+    /// log it.
+    pub load_solution: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Solution {
+    pub code: Vec<Instruction>,
+    /// How many ticks the solution takes to collect the last gem.
+    pub ticks: usize,
+    pub programs_tried: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoSolution {
+    /// There's no level loaded to search in.
+    NoLevel,
+    /// Every program up to the instruction limit was tried.
+    Unsolvable,
+    /// The search ran out of [`MAX_PROGRAMS`] before finding one.
+    GaveUp,
+}
+
+impl fmt::Display for NoSolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NoSolution::NoLevel => write!(f, "no level to solve"),
+            NoSolution::Unsolvable => write!(f, "no program fits in the instruction limit"),
+            NoSolution::GaveUp => write!(f, "gave up after {MAX_PROGRAMS} programs"),
+        }
+    }
+}
+
+/// Triggered when a [`SolveLevel`] search ends without a solution.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct SolveFailed {
+    pub request: SolveLevel,
+    pub reason: NoSolution,
+}
+
+/// A search started by a [`SolveLevel`] that hasn't finished yet.
+#[derive(Component)]
+pub struct SolverTask {
+    request: SolveLevel,
+    task: Task<Result<Solution, NoSolution>>,
+}
+
+/// Every instruction the solver may put in a program of `len` slots: each
+/// unlocked kind of instruction with each of its operands.
+fn candidates(unlocked: &UnlockedInstructions, len: usize) -> Vec<Instruction> {
+    let mut candidates = unlocked.to_vec();
+    candidates = candidates
+        .into_iter()
+        .flat_map(|instruction| match instruction.sensor() {
+            Some(_) => Sensor::ALL
+                .map(|sensor| instruction.with_sensor(sensor))
+                .to_vec(),
+            None => vec![instruction],
+        })
+        .collect();
+    candidates = candidates
+        .into_iter()
+        .flat_map(|instruction| match instruction.action() {
+            Some(_) => [
+                Action::MoveForward,
+                Action::TurnLeft,
+                Action::TurnRight,
+                Action::Jump(0),
+            ]
+            .map(|action| instruction.with_action(action))
+            .to_vec(),
+            None => vec![instruction],
+        })
+        .collect();
+    candidates = candidates
+        .into_iter()
+        .flat_map(|instruction| match instruction.target() {
+            Some(_) => (0..len)
+                .map(|target| instruction.with_target(target))
+                .collect(),
+            None => vec![instruction],
+        })
+        .collect();
+    candidates = candidates
+        .into_iter()
+        .flat_map(|instruction| match instruction.register() {
+            Some(_) => Register::ALL
+                .map(|register| instruction.with_register(register))
+                .to_vec(),
+            None => vec![instruction],
+        })
+        .collect();
+    candidates = candidates
+        .into_iter()
+        .flat_map(|instruction| match instruction.interrupt() {
+            Some(_) => Interrupt::ALL
+                .map(|interrupt| instruction.with_interrupt(interrupt))
+                .to_vec(),
+            None => vec![instruction],
+        })
+        .collect();
+    candidates
+        .into_iter()
+        .flat_map(|instruction| match instruction.value() {
+            Some(_) => (0..=MAX_SOLVER_VALUE)
+                .map(|value| instruction.with_value(value))
+                .collect(),
+            None => vec![instruction],
+        })
+        .collect()
+}
+
+/// Runs `code` on every robot of `simulation` until the bomb goes off, and
+/// returns how many ticks it took to collect every gem, if it did.
+fn run(
+    simulation: &mut Simulation,
+    code: &[Instruction],
+    options: &CpuOptions,
+    time_to_bomb: Duration,
+) -> Option<usize> {
+    for program in &mut simulation.programs {
+        program.clear();
+        program.extend_from_slice(code);
+    }
+    // The first tick happens as the run starts, and the rest every `cpu_tick`.
+    let ticks = time_to_bomb.div_duration_f32(options.cpu_tick).ceil() as usize;
+    let bomb_low = time_to_bomb.saturating_sub(BOMB_LOW_THRESHOLD.div_f32(options.multiplier));
+    for tick in 0..ticks {
+        if options.cpu_tick * tick as u32 > bomb_low {
+            simulation.raise_all(Interrupt::BombLow);
+        }
+        match simulation.step(options).stopped {
+            Some(StopReason::AllGemsCollected) => return Some(tick + 1),
+            Some(_) => return None,
+            None => {}
+        }
+    }
+    None
+}
+
+/// Finds the shortest program that clears the level, trying at most
+/// `max_instructions` slots.
+pub fn solve(
+    mut simulation: Simulation,
+    unlocked: &UnlockedInstructions,
+    max_instructions: usize,
+    options: &CpuOptions,
+    time_to_bomb: Duration,
+) -> Result<Solution, NoSolution> {
+    let initial = simulation.snapshot();
+    let mut programs_tried = 0;
+    for len in 1..=max_instructions {
+        let candidates = candidates(unlocked, len);
+        for code in itertools::repeat_n(candidates.iter().copied(), len).multi_cartesian_product() {
+            // A program with dead code has a shorter twin that was already tried.
+            let analysis = analyze(&code, options, time_to_bomb);
+            if analysis
+                .warnings
+                .iter()
+                .any(|warning| matches!(warning, Warning::Unreachable { .. }))
+            {
+                continue;
+            }
+
+            if programs_tried == MAX_PROGRAMS {
+                return Err(NoSolution::GaveUp);
+            }
+            programs_tried += 1;
+            simulation.restore(&initial);
+            if let Some(ticks) = run(&mut simulation, &code, options, time_to_bomb) {
+                return Ok(Solution {
+                    code,
+                    ticks,
+                    programs_tried,
+                });
+            }
+        }
+    }
+    Err(NoSolution::Unsolvable)
+}
+
+fn solve_level(
+    trigger: Trigger<SolveLevel>,
+    mut commands: Commands,
+    level_grid: Option<Res<LevelGrid>>,
+    unlocked: Option<Res<UnlockedInstructions>>,
+    cpu_options: Option<Res<CpuOptions>>,
+    time_to_bomb: Option<Res<TimeToBomb>>,
+    robots: Query<(&RobotIndex, &GridCoords, &PlayerDirection)>,
+    gems: Query<&GridCoords, With<GemObject>>,
+    crates: Query<&GridCoords, With<CrateObject>>,
+    editor: ProgramEditor,
+) {
+    let request = *trigger.event();
+    let (Some(level_grid), Some(unlocked), Some(cpu_options), Some(time_to_bomb)) =
+        (level_grid, unlocked, cpu_options, time_to_bomb)
+    else {
+        tracing::warn!("No level to solve");
+        commands.trigger(SolveFailed {
+            request,
+            reason: NoSolution::NoLevel,
+        });
+        return;
+    };
+    let mut simulation = Simulation::new(
+        level_grid.clone(),
+        gems.iter().copied().collect(),
+        crates.iter().copied().collect(),
+    );
+    let mut ordered = robots.iter().collect::<Vec<_>>();
+    ordered.sort_by_key(|(index, ..)| **index);
+    for (_, coords, direction) in &ordered {
+        let robot = Robot {
+            coords: **coords,
+            direction: **direction,
+        };
+        simulation.add_robot(robot, vec![]);
+    }
+    let max_instructions = ordered
+        .iter()
+        .filter_map(|(index, ..)| editor.program(index.0))
+        .map(|program_code| program_code.max_instructions)
+        .min()
+        .unwrap_or_default();
+
+    let unlocked = unlocked.clone();
+    let cpu_options = cpu_options.clone();
+    let time_to_bomb = time_to_bomb.duration;
+    let task = AsyncComputeTaskPool::get().spawn(async move {
+        solve(
+            simulation,
+            &unlocked,
+            max_instructions,
+            &cpu_options,
+            time_to_bomb,
+        )
+    });
+    tracing::info!("Searching for a solution");
+    commands.spawn((
+        Name::new("Solver Task"),
+        SolverTask { request, task },
+        StateScoped(GameState::Playing),
+    ));
+}
+
+fn finish_solving(
+    mut commands: Commands,
+    mut tasks: Query<(Entity, &mut SolverTask)>,
+    robots: Query<&RobotIndex>,
+    mut editor: ProgramEditor,
+) {
+    for (entity, mut solver) in &mut tasks {
+        let Some(result) = block_on(future::poll_once(&mut solver.task)) else {
+            continue;
+        };
+        commands.entity(entity).despawn();
+        let request = solver.request;
+        let solution = match result {
+            Ok(solution) => solution,
+            Err(reason) => {
+                tracing::warn!("Couldn't solve the level: {reason}");
+                commands.trigger(SolveFailed { request, reason });
+                continue;
+            }
+        };
+        tracing::info!(
+            "Solved the level in {} instructions and {} ticks, after trying {} programs:\n{}",
+            solution.code.len(),
+            solution.ticks,
+            solution.programs_tried,
+            format_program(&solution.code)
+        );
+
+        if request.load_solution {
+            for index in &robots {
+                editor.replace(index.0, solution.code.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use super::*;
+    use crate::game::cpu::CycleCosts;
+
+    const EAST: PlayerDirection = PlayerDirection(GridCoords { x: 1, y: 0 });
+
+    /// Options where every instruction takes a single cycle.
+    fn options() -> CpuOptions {
+        CpuOptions {
+            cpu_tick: Duration::from_millis(100),
+            multiplier: 1.0,
+            max_call_depth: 4,
+            cycle_costs: CycleCosts(HashMap::new()),
+            loop_program: false,
+        }
+    }
+
+    /// A corridor of floor running east from the origin, with one robot on
+    /// its west end and a gem on `gem`.
+    fn corridor(length: i32, gem: GridCoords) -> Simulation {
+        let grid = LevelGrid {
+            floors: (0..length).map(|x| GridCoords::new(x, 0)).collect(),
+        };
+        let mut simulation = Simulation::new(grid, HashSet::from([gem]), HashSet::new());
+        simulation.add_robot(
+            Robot {
+                coords: GridCoords::new(0, 0),
+                direction: EAST,
+            },
+            vec![],
+        );
+        simulation
+    }
+
+    #[test]
+    fn finds_the_shortest_program() {
+        let unlocked =
+            UnlockedInstructions(vec![Instruction::IfGapTurnLeft, Instruction::MoveForward]);
+        let solution = solve(
+            corridor(3, GridCoords::new(2, 0)),
+            &unlocked,
+            4,
+            &options(),
+            Duration::from_secs(1),
+        )
+        .expect("the corridor is solvable");
+        assert!(matches!(
+            solution.code[..],
+            [Instruction::MoveForward, Instruction::MoveForward]
+        ));
+        assert_eq!(solution.ticks, 2);
+        // Both one-slot programs, then the two-slot programs in order.
+        assert_eq!(solution.programs_tried, 6);
+    }
+
+    #[test]
+    fn gives_up_after_max_programs() {
+        let unlocked = UnlockedInstructions(vec![
+            Instruction::MoveForward,
+            Instruction::IfGapTurnLeft,
+            Instruction::IfWallTurnRight,
+        ]);
+        // The gem is off the floor, and the bomb goes off after one tick, so
+        // every program fails quickly.
+        let options = options();
+        let result = solve(
+            corridor(3, GridCoords::new(5, 5)),
+            &unlocked,
+            12,
+            &options,
+            options.cpu_tick,
+        );
+        assert!(matches!(result, Err(NoSolution::GaveUp)));
+    }
+}
@@ -1,4 +1,5 @@
 use bevy::prelude::*;
+use bevy_ecs_ldtk::GridCoords;
 
 #[cfg(feature = "dev_native")]
 use bevy_simple_subsecond_system::hot;
@@ -6,9 +7,18 @@ use bevy_simple_subsecond_system::hot;
 use crate::{state::ProgramState, theme::interaction::Inactive};
 
 use super::{
-    cpu::{CpuOptions, CpuState},
-    level::{ResetButton, RunButton},
-    objects::{BombTimer, TimeToBomb},
+    analysis::analyze,
+    assembly::format_program,
+    cpu::{CpuOptions, CpuState, ProgramCode},
+    debugger::{DebugButton, program_paused},
+    explosives::CrateObject,
+    level::{LevelGrid, ResetButton, RunButton},
+    objects::{BombTimer, GemObject, TimeToBomb},
+    player::PlayerDirection,
+    rewind::{RewindButton, RunHistory},
+    robots::RobotIndex,
+    simulation::{Robot, Simulation},
+    trace::ExecutionTrace,
 };
 
 pub(super) fn plugin(app: &mut App) {
@@ -17,7 +27,12 @@ pub(super) fn plugin(app: &mut App) {
         .register_type::<TickTimer>();
     app.add_systems(OnEnter(ProgramState::Running), begin_running_program)
         .add_systems(OnEnter(ProgramState::Buying), enter_buying)
-        .add_systems(Update, tick_timer.run_if(in_state(ProgramState::Running)));
+        .add_systems(
+            Update,
+            tick_timer
+                .run_if(in_state(ProgramState::Running))
+                .run_if(not(program_paused)),
+        );
     app.add_observer(tick_printer);
 }
 
@@ -25,11 +40,19 @@ fn enter_buying(
     mut commands: Commands,
     run_button: Query<Entity, With<RunButton>>,
     reset_button: Query<Entity, With<ResetButton>>,
+    debug_buttons: Query<Entity, With<DebugButton>>,
+    rewind_buttons: Query<Entity, With<RewindButton>>,
 ) {
     let run_button = run_button.single().unwrap();
     commands.entity(run_button).remove::<Inactive>();
     let reset_button = reset_button.single().unwrap();
     commands.entity(reset_button).insert(Inactive);
+    for debug_button in &debug_buttons {
+        commands.entity(debug_button).insert(Inactive);
+    }
+    for rewind_button in &rewind_buttons {
+        commands.entity(rewind_button).remove::<Inactive>();
+    }
 }
 
 #[cfg_attr(feature = "dev_native", hot)]
@@ -37,8 +60,20 @@ fn begin_running_program(
     mut commands: Commands,
     cpu_options: Res<CpuOptions>,
     time_to_bomb: Res<TimeToBomb>,
+    level_grid: Res<LevelGrid>,
+    mut robots: Query<(
+        &RobotIndex,
+        &GridCoords,
+        &PlayerDirection,
+        &ProgramCode,
+        &mut CpuState,
+    )>,
+    gems: Query<&GridCoords, With<GemObject>>,
+    crates: Query<&GridCoords, With<CrateObject>>,
     run_button: Query<Entity, With<RunButton>>,
     reset_button: Query<Entity, With<ResetButton>>,
+    debug_buttons: Query<Entity, With<DebugButton>>,
+    rewind_buttons: Query<Entity, With<RewindButton>>,
 ) {
     tracing::info!("Starting the program with CPU options: {:?}", cpu_options);
     commands.insert_resource(TickTimer {
@@ -52,13 +87,51 @@ fn begin_running_program(
             time_to_bomb.duration.mul_f32(cpu_options.multiplier),
             TimerMode::Once,
         ),
+        low: false,
     });
-    commands.insert_resource(CpuState { pc: 0 });
+    let mut simulation = Simulation::new(
+        level_grid.clone(),
+        gems.iter().copied().collect(),
+        crates.iter().copied().collect(),
+    );
+    let mut robots = robots.iter_mut().collect::<Vec<_>>();
+    robots.sort_by_key(|(index, ..)| **index);
+    for (index, coords, direction, program_code, mut cpu_state) in robots {
+        tracing::info!(
+            "Program of robot {}:\n{}",
+            index.0 + 1,
+            format_program(&program_code.code)
+        );
+        let analysis = analyze(&program_code.code, &cpu_options, time_to_bomb.duration);
+        for warning in &analysis.warnings {
+            match warning.slot() {
+                Some(slot) => tracing::warn!("Robot {}, slot {slot}: {warning}", index.0 + 1),
+                None => tracing::warn!("Robot {}: {warning}", index.0 + 1),
+            }
+        }
+        *cpu_state = CpuState::default();
+        let robot = Robot {
+            coords: *coords,
+            direction: *direction,
+        };
+        simulation.add_robot(robot, program_code.code.clone());
+    }
+    commands.insert_resource(RunHistory::new(simulation.snapshot()));
+    commands.insert_resource(ExecutionTrace::new(
+        simulation.robots.iter().map(|state| state.robot).collect(),
+    ));
+    commands.insert_resource(simulation);
     commands.trigger(Tick);
     let run_button = run_button.single().unwrap();
     commands.entity(run_button).insert(Inactive);
     let reset_button = reset_button.single().unwrap();
     commands.entity(reset_button).remove::<Inactive>();
+    for debug_button in &debug_buttons {
+        commands.entity(debug_button).remove::<Inactive>();
+    }
+    for rewind_button in &rewind_buttons {
+        commands.entity(rewind_button).insert(Inactive);
+    }
 }
 
 fn tick_timer(mut commands: Commands, time: Res<Time>, mut timer: ResMut<TickTimer>) {
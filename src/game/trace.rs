@@ -0,0 +1,123 @@
+//! Recording where the robot went during a run, and drawing it as a trail on
+//! the level so a failed run can be inspected after the fact.
+
+use bevy::{color::palettes::css::*, prelude::*, render::view::RenderLayers};
+use bevy_ecs_ldtk::{GridCoords, LevelIid, utils::grid_coords_to_translation};
+
+use crate::state::GameState;
+
+use super::{
+    cpu::Instruction, level::LEVEL_OVERLAY_LAYER, player::PlayerDirection, simulation::Robot,
+};
+
+pub(super) fn plugin(app: &mut App) {
+    app.register_type::<ExecutionTrace>()
+        .register_type::<TraceEntry>();
+    app.init_gizmo_group::<TrailGizmos>().insert_gizmo_config(
+        TrailGizmos,
+        GizmoConfig {
+            render_layers: RenderLayers::layer(LEVEL_OVERLAY_LAYER),
+            ..default()
+        },
+    );
+    app.add_systems(OnExit(GameState::Playing), cleanup_trace);
+    app.add_systems(
+        Update,
+        draw_trail
+            .run_if(resource_exists::<ExecutionTrace>)
+            .run_if(in_state(GameState::Playing)),
+    );
+}
+
+/// An executed instruction, and where the robot was after it.
+#[derive(Reflect, Debug, Clone, Copy)]
+pub struct TraceEntry {
+    /// The [`RobotIndex`](super::robots::RobotIndex) of the robot.
+    pub robot: usize,
+    pub slot: usize,
+    pub instruction: Instruction,
+    pub coords: GridCoords,
+    pub direction: PlayerDirection,
+}
+
+/// Every instruction executed during the current or last run.
+#[derive(Resource, Reflect, Debug, Clone)]
+#[reflect(Resource)]
+pub struct ExecutionTrace {
+    /// Where each robot started the run.
+    pub start: Vec<Robot>,
+    pub entries: Vec<TraceEntry>,
+}
+
+impl ExecutionTrace {
+    pub fn new(start: Vec<Robot>) -> Self {
+        ExecutionTrace {
+            start,
+            entries: vec![],
+        }
+    }
+
+    pub fn record(&mut self, index: usize, slot: usize, instruction: Instruction, robot: Robot) {
+        self.entries.push(TraceEntry {
+            robot: index,
+            slot,
+            instruction,
+            coords: robot.coords,
+            direction: robot.direction,
+        });
+    }
+}
+
+fn cleanup_trace(mut commands: Commands) {
+    commands.remove_resource::<ExecutionTrace>();
+}
+
+/// The trail is drawn over the level, so only the
+/// [`LevelCamera`](super::level::LevelCamera) shows it.
+#[derive(Default, Reflect, GizmoConfigGroup)]
+pub struct TrailGizmos;
+
+/// Colors the trail of each robot, by its place in the execution order.
+const TRAIL_COLORS: [Srgba; 4] = [ORANGE, DEEP_SKY_BLUE, LIME, HOT_PINK];
+
+/// Draws the trace as breadcrumbs that fade out the further back they are.
+fn draw_trail(
+    mut gizmos: Gizmos<TrailGizmos>,
+    trace: Res<ExecutionTrace>,
+    level: Single<&GlobalTransform, With<LevelIid>>,
+) {
+    // Grid coordinates are relative to the level, wherever it was spawned.
+    let to_world = |coords: GridCoords| {
+        let translation = grid_coords_to_translation(coords, IVec2::splat(18));
+        level.transform_point(translation.extend(0.0)).truncate()
+    };
+
+    for (robot, start) in trace.start.iter().enumerate() {
+        let base = Color::from(TRAIL_COLORS[robot % TRAIL_COLORS.len()]);
+        let color = |age: f32| base.with_alpha(1.0 - 0.85 * age);
+        let entries = trace
+            .entries
+            .iter()
+            .filter(|entry| entry.robot == robot)
+            .collect::<Vec<_>>();
+
+        let steps = entries.len().max(1) as f32;
+        let mut from = to_world(start.coords);
+        gizmos.circle_2d(from, 3.0, color(1.0));
+        for (i, entry) in entries.iter().enumerate() {
+            let to = to_world(entry.coords);
+            let color = color(1.0 - (i + 1) as f32 / steps);
+            if to != from {
+                gizmos.line_2d(from, to, color);
+                gizmos.circle_2d(to, 2.0, color);
+            }
+            from = to;
+        }
+
+        let last = entries
+            .last()
+            .map_or(start.direction, |entry| entry.direction);
+        let facing = Vec2::new(last.0.x as f32, last.0.y as f32);
+        gizmos.arrow_2d(from, from + facing * 6.0, color(0.0));
+    }
+}
@@ -13,8 +13,11 @@ use crate::{
 };
 
 use super::{
-    cpu::{Instruction, ProgramCode, UnlockedInstructions},
+    cpu::{Instruction, InstructionType, Interrupt, ProgramCode, Register, UnlockedInstructions},
+    instructions::InstructionDefinitions,
     player::Wallet,
+    robots::RobotPrograms,
+    solver::{SolveFailed, SolveLevel},
 };
 
 pub(crate) fn plugin(app: &mut App) {
@@ -33,7 +36,8 @@ pub(crate) fn plugin(app: &mut App) {
             .run_if(resource_exists_and_changed::<Wallet>)
             .run_if(in_state(GameState::Playing)),
     );
-    app.add_observer(apply_upgrade);
+    app.add_observer(apply_upgrade)
+        .add_observer(refund_solver_hint);
 }
 
 #[derive(Component, Reflect, Debug, Clone, Copy, Deref, DerefMut)]
@@ -50,6 +54,15 @@ pub enum UpgradeType {
     CpuMultiplier,
     MaxInstructions,
     UnlockIf,
+    UnlockJumps,
+    UnlockRegisters,
+    UnlockCalls,
+    MaxCallDepth,
+    FasterMotors,
+    UnlockInterrupts,
+    SolverHint,
+    LoopProgram,
+    UnlockDetonate,
 }
 
 impl std::fmt::Display for UpgradeType {
@@ -59,6 +72,15 @@ impl std::fmt::Display for UpgradeType {
             UpgradeType::CpuMultiplier => write!(f, "CPU Multiplier x2"),
             UpgradeType::MaxInstructions => write!(f, "Max Instructions x2"),
             UpgradeType::UnlockIf => write!(f, "Unlock If"),
+            UpgradeType::UnlockJumps => write!(f, "Unlock Jumps"),
+            UpgradeType::UnlockRegisters => write!(f, "Unlock Registers"),
+            UpgradeType::UnlockCalls => write!(f, "Unlock Calls"),
+            UpgradeType::MaxCallDepth => write!(f, "Max Call Depth x2"),
+            UpgradeType::FasterMotors => write!(f, "Faster Motors"),
+            UpgradeType::UnlockInterrupts => write!(f, "Unlock Interrupts"),
+            UpgradeType::SolverHint => write!(f, "Solver Hint"),
+            UpgradeType::LoopProgram => write!(f, "Loop Program"),
+            UpgradeType::UnlockDetonate => write!(f, "Unlock Detonate"),
         }
     }
 }
@@ -92,6 +114,10 @@ impl Upgrade {
         Self::new(UpgradeType::MaxInstructions, level, cost)
     }
 
+    fn max_call_depth(level: u32, cost: usize) -> Self {
+        Self::new(UpgradeType::MaxCallDepth, level, cost)
+    }
+
     fn new(upgrade_type: UpgradeType, level: u32, cost: usize) -> Self {
         Upgrade {
             upgrade_type,
@@ -122,13 +148,36 @@ impl Default for UpgradeTree {
             .map(|u| deps.add_node(u))
             .collect::<Vec<_>>();
         let unlock_if = deps.add_node(Upgrade::new(UpgradeType::UnlockIf, 1, 100));
+        let unlock_jumps = deps.add_node(Upgrade::new(UpgradeType::UnlockJumps, 1, 150));
+        let unlock_registers = deps.add_node(Upgrade::new(UpgradeType::UnlockRegisters, 1, 200));
+        let unlock_calls = deps.add_node(Upgrade::new(UpgradeType::UnlockCalls, 1, 250));
+        let unlock_interrupts = deps.add_node(Upgrade::new(UpgradeType::UnlockInterrupts, 1, 300));
+        let max_call_depths = (1_u32..=3)
+            .map(|i| Upgrade::max_call_depth(i, 100 * 2_usize.pow(i)))
+            .map(|u| deps.add_node(u))
+            .collect::<Vec<_>>();
+        let faster_motors = deps.add_node(Upgrade::new(UpgradeType::FasterMotors, 1, 80));
+        let solver_hint = deps.add_node(Upgrade::new(UpgradeType::SolverHint, 1, 500));
+        let loop_program = deps.add_node(Upgrade::new(UpgradeType::LoopProgram, 1, 120));
+        let unlock_detonate = deps.add_node(Upgrade::new(UpgradeType::UnlockDetonate, 1, 180));
 
         deps.add_edge(max_insts[0], cpu_speeds[0], ());
+        deps.add_edge(cpu_speeds[0], faster_motors, ());
+        deps.add_edge(cpu_speeds[0], loop_program, ());
         deps.add_edge(cpu_speeds[0], cpu_speeds[1], ());
         deps.add_edge(cpu_speeds[0], max_insts[1], ());
         deps.add_edge(cpu_speeds[1], cpu_speeds[2], ());
         deps.add_edge(cpu_speeds[1], max_insts[2], ());
         deps.add_edge(cpu_speeds[1], unlock_if, ());
+        deps.add_edge(unlock_if, unlock_jumps, ());
+        deps.add_edge(unlock_if, unlock_detonate, ());
+        deps.add_edge(unlock_jumps, unlock_registers, ());
+        deps.add_edge(unlock_jumps, unlock_calls, ());
+        deps.add_edge(unlock_jumps, solver_hint, ());
+        deps.add_edge(unlock_calls, max_call_depths[0], ());
+        deps.add_edge(unlock_calls, unlock_interrupts, ());
+        deps.add_edge(max_call_depths[0], max_call_depths[1], ());
+        deps.add_edge(max_call_depths[1], max_call_depths[2], ());
         deps.add_edge(cpu_speeds[2], cpu_speeds[3], ());
         deps.add_edge(cpu_speeds[2], max_insts[3], ());
         deps.add_edge(cpu_speeds[3], cpu_speeds[4], ());
@@ -252,9 +301,12 @@ fn activate_upgrade_buttons(
 
 fn apply_upgrade(
     trigger: Trigger<UpgradeBought>,
+    mut commands: Commands,
     mut cpu_options: ResMut<CpuOptions>,
-    mut program_code: ResMut<ProgramCode>,
+    mut robot_programs: ResMut<RobotPrograms>,
+    mut robots: Query<&mut ProgramCode>,
     mut unlocked_instructions: ResMut<UnlockedInstructions>,
+    definitions: Res<InstructionDefinitions>,
 ) {
     match trigger.event().upgrade_type {
         UpgradeType::CpuSpeed => {
@@ -272,18 +324,192 @@ fn apply_upgrade(
             );
         }
         UpgradeType::MaxInstructions => {
-            program_code.max_instructions *= 2;
+            robot_programs.for_each_mut(|program_code| program_code.max_instructions *= 2);
+            for mut program_code in &mut robots {
+                program_code.max_instructions *= 2;
+            }
             tracing::info!(
                 "Applied Max Instructions upgrade: new max instructions = {}",
-                program_code.max_instructions
+                robot_programs.template.max_instructions
             );
         }
         UpgradeType::UnlockIf => {
-            unlocked_instructions.0.insert(
-                Instruction::IfGapTurnLeft.inst_type(),
+            for instruction in [
                 Instruction::IfGapTurnLeft,
+                Instruction::IfWallTurnRight,
+                Instruction::If(default(), default()),
+            ] {
+                unlocked_instructions.unlock(instruction);
+            }
+            tracing::info!(
+                "Applied Unlock If upgrade: now unlocked IfGapTurnLeft, IfWallTurnRight and If instructions"
+            );
+        }
+        UpgradeType::UnlockJumps => {
+            for instruction in [
+                Instruction::Jump(0),
+                Instruction::JumpIfBlocked(0),
+                Instruction::Halt,
+            ] {
+                unlocked_instructions.unlock(instruction);
+            }
+            tracing::info!("Applied Unlock Jumps upgrade: now unlocked control flow instructions");
+        }
+        UpgradeType::UnlockRegisters => {
+            for instruction in [
+                Instruction::Set(Register::R0, 0),
+                Instruction::Inc(Register::R0),
+                Instruction::Dec(Register::R0),
+                Instruction::JumpIfZero(Register::R0, 0),
+            ] {
+                unlocked_instructions.unlock(instruction);
+            }
+            tracing::info!("Applied Unlock Registers upgrade: now unlocked register instructions");
+        }
+        UpgradeType::UnlockCalls => {
+            for instruction in [Instruction::Call(0), Instruction::Return] {
+                unlocked_instructions.unlock(instruction);
+            }
+            tracing::info!(
+                "Applied Unlock Calls upgrade: now unlocked Call and Return instructions"
+            );
+        }
+        UpgradeType::UnlockInterrupts => {
+            let instruction = Instruction::OnInterrupt(Interrupt::default(), 0);
+            unlocked_instructions.unlock(instruction);
+            tracing::info!(
+                "Applied Unlock Interrupts upgrade: now unlocked OnInterrupt instruction"
+            );
+        }
+        UpgradeType::MaxCallDepth => {
+            cpu_options.max_call_depth *= 2;
+            tracing::info!(
+                "Applied Max Call Depth upgrade: new max call depth = {}",
+                cpu_options.max_call_depth
+            );
+        }
+        UpgradeType::FasterMotors => {
+            for (mnemonic, definition) in &definitions.instructions {
+                if definition.category != InstructionType::Movement {
+                    continue;
+                }
+                if let Some(cycles) = cpu_options.cycle_costs.get_mut(mnemonic) {
+                    *cycles = cycles.saturating_sub(1).max(1);
+                }
+            }
+            tracing::info!(
+                "Applied Faster Motors upgrade: new movement cost = {:?} cycles",
+                cpu_options.cycle_costs.cost(&Instruction::MoveForward)
             );
-            tracing::info!("Applied Unlock If upgrade: now unlocked IfGapTurnLeft instruction");
         }
+        UpgradeType::LoopProgram => {
+            cpu_options.loop_program = true;
+            tracing::info!("Applied Loop Program upgrade: programs now start over at the end");
+        }
+        UpgradeType::SolverHint => {
+            commands.trigger(SolveLevel {
+                load_solution: true,
+            });
+            tracing::info!("Applied Solver Hint upgrade: searching for the shortest program");
+        }
+        UpgradeType::UnlockDetonate => {
+            unlocked_instructions.unlock(Instruction::Detonate);
+            tracing::info!("Applied Unlock Detonate upgrade: now unlocked Detonate instruction");
+        }
+    }
+}
+
+/// A Solver Hint that finds nothing is refunded, and can be bought again once
+/// more is unlocked. The hint is the only search that loads its solution.
+fn refund_solver_hint(
+    trigger: Trigger<SolveFailed>,
+    mut commands: Commands,
+    parent: Single<Entity, With<UpgradeParent>>,
+    mut upgrade_tree: ResMut<UpgradeTree>,
+    mut wallet: ResMut<Wallet>,
+) {
+    let SolveFailed { request, reason } = *trigger.event();
+    if !request.load_solution {
+        return;
+    }
+    let Some(idx) = upgrade_tree.deps.node_indices().find(|idx| {
+        let upgrade = &upgrade_tree.deps[*idx];
+        upgrade.upgrade_type == UpgradeType::SolverHint && upgrade.bought
+    }) else {
+        return;
+    };
+    let upgrade = &mut upgrade_tree.deps[idx];
+    upgrade.bought = false;
+    wallet.gems += upgrade.cost;
+    tracing::info!(
+        "Refunded {} gems for the Solver Hint: {reason}",
+        upgrade.cost
+    );
+    commands.entity(*parent).with_children(|parent| {
+        spawn_upgrade_button(parent, &mut upgrade_tree, idx);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::solver::NoSolution;
+
+    fn solver_hint(upgrade_tree: &UpgradeTree) -> petgraph::graph::NodeIndex {
+        upgrade_tree
+            .deps
+            .node_indices()
+            .find(|idx| upgrade_tree.deps[*idx].upgrade_type == UpgradeType::SolverHint)
+            .expect("the tree has a Solver Hint")
+    }
+
+    #[test]
+    fn failed_solve_refunds_the_solver_hint() {
+        let mut upgrade_tree = UpgradeTree::default();
+        let idx = solver_hint(&upgrade_tree);
+        upgrade_tree.deps[idx].bought = true;
+        let cost = upgrade_tree.deps[idx].cost;
+
+        let mut world = World::new();
+        world.insert_resource(upgrade_tree);
+        world.insert_resource(Wallet { gems: 0 });
+        world.spawn(UpgradeParent);
+        world.add_observer(refund_solver_hint);
+        world.trigger(SolveFailed {
+            request: SolveLevel {
+                load_solution: true,
+            },
+            reason: NoSolution::GaveUp,
+        });
+        world.flush();
+
+        assert_eq!(world.resource::<Wallet>().gems, cost);
+        assert!(!world.resource::<UpgradeTree>().deps[idx].bought);
+        // The hint can be bought again.
+        let mut nodes = world.query::<&UpgradeNode>();
+        assert!(nodes.iter(&world).any(|node| **node == idx));
+    }
+
+    #[test]
+    fn failed_solve_without_loading_keeps_the_hint() {
+        let mut upgrade_tree = UpgradeTree::default();
+        let idx = solver_hint(&upgrade_tree);
+        upgrade_tree.deps[idx].bought = true;
+
+        let mut world = World::new();
+        world.insert_resource(upgrade_tree);
+        world.insert_resource(Wallet { gems: 0 });
+        world.spawn(UpgradeParent);
+        world.add_observer(refund_solver_hint);
+        world.trigger(SolveFailed {
+            request: SolveLevel {
+                load_solution: false,
+            },
+            reason: NoSolution::GaveUp,
+        });
+        world.flush();
+
+        assert_eq!(world.resource::<Wallet>().gems, 0);
+        assert!(world.resource::<UpgradeTree>().deps[idx].bought);
     }
 }